@@ -0,0 +1,89 @@
+//! Compares the two value representations. Run it once as is and once with
+//! `--features nan_boxing`:
+//!
+//!     cargo bench --bench value
+//!     cargo bench --bench value --features nan_boxing
+
+use std::hint::black_box;
+use std::mem::size_of;
+use std::time::Instant;
+
+use rlox::value::{Value, ValueArray};
+
+const VALUES: usize = 1 << 16;
+const ROUNDS: usize = 200;
+
+fn bench(name: &str, mut f: impl FnMut()) {
+    // One untimed round to warm up caches
+    f();
+    let start = Instant::now();
+    for _ in 0..ROUNDS {
+        f();
+    }
+    let per_round = start.elapsed() / ROUNDS as u32;
+    println!("{:24} {:>10.3?} per round", name, per_round);
+}
+
+fn mixed_values() -> Vec<Value> {
+    (0..VALUES as i64)
+        .map(|i| match i % 4 {
+            0 => Value::from(i),
+            1 => Value::from(i as f64 * 0.5),
+            2 => Value::from(i % 3 == 0),
+            _ => Value::NIL,
+        })
+        .collect()
+}
+
+fn main() {
+    let representation = if cfg!(feature = "nan_boxing") {
+        "nan_boxing"
+    } else {
+        "tagged enum"
+    };
+    println!(
+        "value representation: {} ({} bytes)",
+        representation,
+        size_of::<Value>()
+    );
+
+    let values = mixed_values();
+
+    bench("construct", || {
+        for i in 0..VALUES as i64 {
+            black_box(Value::from(black_box(i)));
+            black_box(Value::from(black_box(i as f64)));
+        }
+    });
+
+    bench("sum numeric", || {
+        let mut sum = 0.0;
+        for value in &values {
+            if value.is_numeric() {
+                sum += value.as_numeric();
+            }
+        }
+        black_box(sum);
+    });
+
+    bench("falsey", || {
+        let count = values
+            .iter()
+            .filter(|value| value.is_nil() || (value.is_bool() && !value.as_bool()))
+            .count();
+        black_box(count);
+    });
+
+    bench("equality", || {
+        let count = values.windows(2).filter(|pair| pair[0] == pair[1]).count();
+        black_box(count);
+    });
+
+    bench("value array write", || {
+        let mut array = ValueArray::new();
+        for value in &values {
+            array.write(*value);
+        }
+        black_box(array.as_slice().len());
+    });
+}
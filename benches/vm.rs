@@ -0,0 +1,154 @@
+//! End-to-end VM benchmarks, reporting the time and heap allocations per
+//! iteration of each workload:
+//!
+//!     cargo bench --bench vm
+//!
+//! The language only has expressions so far, so each workload is one large
+//! expression. The classic Lox benchmarks (fib, binary_trees, method calls,
+//! zoo, instantiation) need functions, variables and classes and should be
+//! added here as those land.
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::hint::black_box;
+use std::io;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Instant;
+
+use rlox::ast::parse;
+use rlox::chunk::Chunk;
+use rlox::compiler::compile;
+use rlox::register::{generate, RegisterVM};
+use rlox::verify::verify;
+use rlox::vm::{InterpretResult, VM};
+
+struct CountingAlloc;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+static BYTES: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for CountingAlloc {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        BYTES.fetch_add(layout.size(), Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        BYTES.fetch_add(new_size.saturating_sub(layout.size()), Ordering::Relaxed);
+        System.realloc(ptr, layout, new_size)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: CountingAlloc = CountingAlloc;
+
+const ITERATIONS: u32 = 2000;
+
+fn bench(name: &str, mut f: impl FnMut()) {
+    f();
+    let allocations = ALLOCATIONS.load(Ordering::Relaxed);
+    let bytes = BYTES.load(Ordering::Relaxed);
+    let start = Instant::now();
+    for _ in 0..ITERATIONS {
+        f();
+    }
+    let elapsed = start.elapsed() / ITERATIONS;
+    let allocations = (ALLOCATIONS.load(Ordering::Relaxed) - allocations) / ITERATIONS as usize;
+    let bytes = (BYTES.load(Ordering::Relaxed) - bytes) / ITERATIONS as usize;
+    println!(
+        "{:26} {:>10.2?}/iter {:>6} allocs/iter {:>8} bytes/iter",
+        name, elapsed, allocations, bytes
+    );
+}
+
+/// Hide a literal from the compiler's constant folding so the VM still has
+/// to evaluate the expression around it
+fn opaque(term: String) -> String {
+    format!("(nil ?? {})", term)
+}
+
+/// Join `count` generated terms with a cycle of operators
+fn expression(count: usize, term: impl Fn(usize) -> String, ops: &[&str]) -> String {
+    let mut source = term(1);
+    for i in 2..=count {
+        source.push_str(ops[i % ops.len()]);
+        source.push_str(&term(i));
+    }
+    source
+}
+
+fn compiled(source: &str) -> Chunk {
+    let mut chunk = Chunk::new();
+    assert!(compile(source, &mut chunk), "benchmark failed to compile");
+    chunk
+}
+
+/// Time the run loop alone; verification is benchmarked separately
+fn bench_run(name: &str, source: &str) {
+    let chunk = compiled(source);
+    verify(&chunk).unwrap();
+    let mut vm = VM::new();
+    vm.set_output(io::sink());
+    bench(name, || {
+        let result = unsafe { vm.interpret_chunk_unchecked(black_box(&chunk)) };
+        assert_eq!(result, InterpretResult::Ok);
+    });
+}
+
+/// The same as `bench_run`, on the register VM
+fn bench_register(name: &str, source: &str) {
+    let program = generate(&parse(source).unwrap()).unwrap();
+    let mut vm = RegisterVM::new();
+    vm.set_output(io::sink());
+    bench(&format!("register/{}", name), || {
+        assert_eq!(vm.run(black_box(&program)), InterpretResult::Ok);
+    });
+}
+
+fn main() {
+    let arithmetic = expression(
+        240,
+        |i| opaque(i.to_string()),
+        &[" + ", " * ", " - ", " % "],
+    );
+    let float_arithmetic = expression(240, |i| opaque(format!("{}.5", i)), &[" + ", " * ", " - "]);
+    let conditionals = expression(
+        60,
+        |i| format!("({} < {} ? {} : nil ?? {})", i, i % 7, i, i),
+        &[" + "],
+    );
+    let bitwise = expression(
+        100,
+        |i| format!("({} << {})", opaque(i.to_string()), i % 8),
+        &[" & ", " | ", " ^ "],
+    );
+    let strings = expression(100, |i| opaque(format!("\"s{}\"", i)), &[" + "]);
+
+    let workloads = [
+        ("arithmetic", &arithmetic),
+        ("float_arithmetic", &float_arithmetic),
+        ("conditionals", &conditionals),
+        ("bitwise", &bitwise),
+        ("string_concat", &strings),
+    ];
+    for (name, source) in workloads {
+        bench_run(name, source);
+    }
+    for (name, source) in workloads {
+        bench_register(name, source);
+    }
+
+    let chunk = compiled(&arithmetic);
+    bench("verify", || {
+        verify(black_box(&chunk)).unwrap();
+    });
+
+    bench("compile", || {
+        black_box(compiled(black_box(&arithmetic)));
+    });
+}
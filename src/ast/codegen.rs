@@ -0,0 +1,254 @@
+use std::fmt::Display;
+
+use super::{BinaryOp, Expr, ExprKind, Literal, Script, UnaryOp};
+use crate::chunk::{BuildError, Chunk, ChunkBuilder, Instruction, OpCode};
+use crate::fold;
+use crate::object::Obj;
+use crate::value::Value;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CodegenError {
+    pub error: BuildError,
+    pub line: u32,
+}
+
+impl Display for CodegenError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "[line {}] Error: {}", self.line, self.error)
+    }
+}
+
+impl UnaryOp {
+    fn opcode(self) -> OpCode {
+        match self {
+            Self::Not => OpCode::Not,
+            Self::Negate => OpCode::Negate,
+            Self::BitNot => OpCode::BitNot,
+        }
+    }
+}
+
+impl BinaryOp {
+    fn opcode(self) -> OpCode {
+        match self {
+            Self::Equal => OpCode::Equal,
+            Self::NotEqual => OpCode::NotEqual,
+            Self::Greater => OpCode::Greater,
+            Self::GreaterEqual => OpCode::GreaterEqual,
+            Self::Less => OpCode::Less,
+            Self::LessEqual => OpCode::LessEqual,
+            Self::Add => OpCode::Add,
+            Self::Subtract => OpCode::Subtract,
+            Self::Multiply => OpCode::Multiply,
+            Self::Divide => OpCode::Divide,
+            Self::IntDivide => OpCode::IntDivide,
+            Self::Modulo => OpCode::Modulo,
+            Self::Power => OpCode::Power,
+            Self::BitAnd => OpCode::BitAnd,
+            Self::BitOr => OpCode::BitOr,
+            Self::BitXor => OpCode::BitXor,
+            Self::ShiftLeft => OpCode::ShiftLeft,
+            Self::ShiftRight => OpCode::ShiftRight,
+        }
+    }
+}
+
+impl Literal {
+    fn to_value(&self) -> Value {
+        match self {
+            Self::Nil => Value::NIL,
+            Self::Bool(b) => (*b).into(),
+            Self::Int(n) => (*n).into(),
+            Self::Number(n) => (*n).into(),
+            Self::String(s) => Obj::copy_str(s).into(),
+        }
+    }
+
+    fn from_value(value: Value) -> Self {
+        if value.is_nil() {
+            Self::Nil
+        } else if value.is_bool() {
+            Self::Bool(value.as_bool())
+        } else if value.is_int() {
+            Self::Int(value.as_int())
+        } else if value.is_number() {
+            Self::Number(value.as_number())
+        } else {
+            Self::String(value.as_string().as_ruststr().to_owned())
+        }
+    }
+}
+
+/// The literal an expression has been reduced to, looking through brackets
+fn literal_of(expr: &Expr) -> Option<&Literal> {
+    match &expr.kind {
+        ExprKind::Literal(literal) => Some(literal),
+        ExprKind::Grouping(inner) => literal_of(inner),
+        _ => None,
+    }
+}
+
+/// Replace operators on literals with their result, as the compiler does
+/// while it emits. Brackets are kept so the result is attributed to the
+/// same line.
+pub(crate) fn fold_constants(expr: &Expr) -> Expr {
+    let kind = match &expr.kind {
+        ExprKind::Literal(literal) => ExprKind::Literal(literal.clone()),
+        ExprKind::Grouping(inner) => ExprKind::Grouping(Box::new(fold_constants(inner))),
+        ExprKind::Unary { op, operand } => {
+            let operand = fold_constants(operand);
+            let folded = literal_of(&operand)
+                .and_then(|a| fold::unary(op.opcode(), a.to_value()))
+                .map(Literal::from_value);
+            match folded {
+                Some(literal) => ExprKind::Literal(literal),
+                None => ExprKind::Unary {
+                    op: *op,
+                    operand: Box::new(operand),
+                },
+            }
+        }
+        ExprKind::Binary { op, left, right } => {
+            let left = fold_constants(left);
+            let right = fold_constants(right);
+            let folded = literal_of(&left)
+                .zip(literal_of(&right))
+                .and_then(|(a, b)| fold::binary(op.opcode(), a.to_value(), b.to_value()))
+                .map(Literal::from_value);
+            match folded {
+                Some(literal) => ExprKind::Literal(literal),
+                None => ExprKind::Binary {
+                    op: *op,
+                    left: Box::new(left),
+                    right: Box::new(right),
+                },
+            }
+        }
+        ExprKind::Conditional {
+            condition,
+            then_branch,
+            else_branch,
+            question,
+        } => ExprKind::Conditional {
+            condition: Box::new(fold_constants(condition)),
+            then_branch: Box::new(fold_constants(then_branch)),
+            else_branch: Box::new(fold_constants(else_branch)),
+            question: *question,
+        },
+        ExprKind::Coalesce {
+            left,
+            right,
+            operator,
+        } => ExprKind::Coalesce {
+            left: Box::new(fold_constants(left)),
+            right: Box::new(fold_constants(right)),
+            operator: *operator,
+        },
+    };
+    Expr {
+        kind,
+        span: expr.span,
+    }
+}
+
+struct Generator {
+    builder: ChunkBuilder,
+}
+
+impl Generator {
+    // Each instruction is attributed to the line the compiler would have
+    // just consumed when emitting it: an operator to the end of its right
+    // operand and a jump to the token that introduced it.
+    fn expression(&mut self, expr: &Expr) -> Result<(), CodegenError> {
+        let line = expr.span.end_line;
+        let error = |error| CodegenError { error, line };
+
+        match &expr.kind {
+            ExprKind::Literal(literal) => {
+                let instruction = match literal {
+                    Literal::Nil => Instruction::Nil,
+                    Literal::Bool(true) => Instruction::True,
+                    Literal::Bool(false) => Instruction::False,
+                    _ => Instruction::Constant(
+                        self.builder
+                            .add_constant(literal.to_value())
+                            .map_err(error)?,
+                    ),
+                };
+                self.builder.emit(instruction, line);
+            }
+            ExprKind::Grouping(inner) => self.expression(inner)?,
+            ExprKind::Unary { op, operand } => {
+                self.expression(operand)?;
+                self.emit_op(op.opcode(), line);
+            }
+            ExprKind::Binary { op, left, right } => {
+                self.expression(left)?;
+                self.expression(right)?;
+                self.emit_op(op.opcode(), line);
+            }
+            ExprKind::Conditional {
+                condition,
+                then_branch,
+                else_branch,
+                question,
+            } => {
+                let else_label = self.builder.new_label();
+                let end_label = self.builder.new_label();
+                let then_line = then_branch.span.end_line;
+
+                self.expression(condition)?;
+                self.builder
+                    .jump(OpCode::JumpIfFalse, else_label, question.line)
+                    .map_err(error)?;
+                self.builder.emit(Instruction::Pop, question.line);
+                self.expression(then_branch)?;
+                self.builder
+                    .jump(OpCode::Jump, end_label, then_line)
+                    .map_err(error)?;
+
+                self.builder.bind(else_label).map_err(error)?;
+                self.builder.emit(Instruction::Pop, then_line);
+                self.expression(else_branch)?;
+                self.builder.bind(end_label).map_err(error)?;
+            }
+            ExprKind::Coalesce {
+                left,
+                right,
+                operator,
+            } => {
+                let end_label = self.builder.new_label();
+
+                self.expression(left)?;
+                self.builder
+                    .jump(OpCode::JumpIfNotNil, end_label, operator.line)
+                    .map_err(error)?;
+                self.builder.emit(Instruction::Pop, operator.line);
+                self.expression(right)?;
+                self.builder.bind(end_label).map_err(error)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn emit_op(&mut self, op: OpCode, line: u32) {
+        let instruction = Instruction::decode(&[op.into()], 0).expect("operators have no operands");
+        self.builder.emit(instruction, line);
+    }
+}
+
+/// Generate the chunk for a script. Constants are folded just as they are
+/// by the single-pass compiler.
+pub fn generate(script: &Script) -> Result<Chunk, CodegenError> {
+    let mut generator = Generator {
+        builder: ChunkBuilder::new(),
+    };
+    generator.expression(&fold_constants(&script.body))?;
+    generator.builder.emit(Instruction::Return, script.eof.line);
+
+    let error = |error| CodegenError {
+        error,
+        line: script.eof.line,
+    };
+    generator.builder.finish().map_err(error)
+}
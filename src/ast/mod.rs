@@ -0,0 +1,242 @@
+mod codegen;
+mod parser;
+
+use crate::chunk::Chunk;
+
+pub(crate) use codegen::fold_constants;
+pub use codegen::{generate, CodegenError};
+pub use parser::parse;
+
+/// Where a node came from: a byte range of the source and the lines its
+/// first and last tokens are on
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+    pub line: u32,
+    pub end_line: u32,
+}
+
+impl Span {
+    /// The span from the start of `self` to the end of `other`
+    pub fn to(self, other: Span) -> Span {
+        Span {
+            start: self.start,
+            end: other.end,
+            line: self.line,
+            end_line: other.end_line,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Literal {
+    Nil,
+    Bool(bool),
+    Int(i64),
+    Number(f64),
+    /// The contents of a string, without its quotes
+    String(String),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UnaryOp {
+    Not,
+    Negate,
+    BitNot,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BinaryOp {
+    Equal,
+    NotEqual,
+    Greater,
+    GreaterEqual,
+    Less,
+    LessEqual,
+    Add,
+    Subtract,
+    Multiply,
+    Divide,
+    IntDivide,
+    Modulo,
+    Power,
+    BitAnd,
+    BitOr,
+    BitXor,
+    ShiftLeft,
+    ShiftRight,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Expr {
+    pub kind: ExprKind,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ExprKind {
+    Literal(Literal),
+    Grouping(Box<Expr>),
+    Unary {
+        op: UnaryOp,
+        operand: Box<Expr>,
+    },
+    Binary {
+        op: BinaryOp,
+        left: Box<Expr>,
+        right: Box<Expr>,
+    },
+    Conditional {
+        condition: Box<Expr>,
+        then_branch: Box<Expr>,
+        else_branch: Box<Expr>,
+        /// The `?` token
+        question: Span,
+    },
+    Coalesce {
+        left: Box<Expr>,
+        right: Box<Expr>,
+        /// The `??` token
+        operator: Span,
+    },
+}
+
+/// A whole program. The language has no statements yet, so a script is a
+/// single expression whose value is the result.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Script {
+    pub body: Expr,
+    /// The end of the input, which the final return is attributed to
+    pub eof: Span,
+}
+
+/// Compile through the AST instead of the single-pass compiler. The chunk
+/// is the same either way; this path exists so tools can work on the tree.
+pub fn compile(source: &str, chunk: &mut Chunk) -> bool {
+    let Some(script) = parse(source) else {
+        return false;
+    };
+    match generate(&script) {
+        Ok(generated) => {
+            *chunk = generated;
+            true
+        }
+        Err(error) => {
+            eprintln!("{}", error);
+            false
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::compiler;
+    use crate::serialize::serialize;
+
+    const CORPUS: &[&str] = &[
+        "1",
+        "-1",
+        "nil",
+        "!true",
+        "\"a\" + \"b\"",
+        "1 + 2 * 3 - 4 / 5 % 6",
+        "7 \\ 2 * 3 \\ (nil ?? 2)",
+        "2 ** 3 ** 2",
+        "-2 ** 2",
+        "2 ** -1",
+        "1 & 2 | 3 ^ 4 << 1 >> 2",
+        "~0 == -1 != false",
+        "1 < 2 <= 3 > 4 >= 5",
+        "0.1 + 0.2",
+        "1 / 0",
+        "-\"x\"",
+        "1 << 64",
+        "\"a\" + 1",
+        "(1 + 2) * (3 + \"c\")",
+        "1 < 2 ?\n\"yes\" : nil",
+        "true ? 1 : false ? 2 : 3",
+        "nil ?? 1 + 2",
+        "(nil ?? 1) + 2 + 3",
+        "(nil ?? 1) ?? (false ? 2 : nil) ?? 3",
+        "(\n1 +\n2\n)\n* -(\n3)\n",
+        "\"multi\nline\" + (nil ?? \"x\")",
+        "(true ? 1 : 2) + (3 + 4)",
+    ];
+
+    #[test]
+    fn test_matches_the_compiler() {
+        for source in CORPUS {
+            let mut expected = Chunk::new();
+            assert!(compiler::compile(source, &mut expected), "{}", source);
+            let mut actual = Chunk::new();
+            assert!(compile(source, &mut actual), "{}", source);
+            assert_eq!(serialize(&actual), serialize(&expected), "{:?}", source);
+        }
+    }
+
+    #[test]
+    fn test_rejects_what_the_compiler_rejects() {
+        for source in [
+            "",
+            "1 +",
+            "(1",
+            "1 ? 2",
+            "1 2",
+            "1 $ 2",
+            "99999999999999999999",
+            "+1",
+            "?? 1",
+            "& 1",
+            "** 2",
+            "1 + * 2",
+        ] {
+            let mut chunk = Chunk::new();
+            assert!(!compiler::compile(source, &mut chunk), "{}", source);
+            assert_eq!(parse(source), None, "{}", source);
+        }
+        // Parses, but needs more stack than the VM has
+        let deep = format!(
+            "{}true{}",
+            "(nil ?? true) == (".repeat(300),
+            ")".repeat(300)
+        );
+        let mut chunk = Chunk::new();
+        assert!(!compiler::compile(&deep, &mut chunk));
+        assert!(!compile(&deep, &mut chunk));
+    }
+
+    #[test]
+    fn test_spans() {
+        let script = parse("(1 +\n 2) ?? 3").unwrap();
+        let ExprKind::Coalesce {
+            left,
+            right,
+            operator,
+        } = script.body.kind
+        else {
+            panic!("expected ??");
+        };
+
+        let span = |start, end, line, end_line| Span {
+            start,
+            end,
+            line,
+            end_line,
+        };
+        assert_eq!(script.body.span, span(0, 13, 1, 2));
+        assert_eq!(left.span, span(0, 8, 1, 2));
+        assert_eq!(operator, span(9, 11, 2, 2));
+        assert_eq!(right.kind, ExprKind::Literal(Literal::Int(3)));
+        assert_eq!(right.span, span(12, 13, 2, 2));
+        assert!(matches!(
+            left.kind,
+            ExprKind::Grouping(inner) if matches!(
+                inner.kind,
+                ExprKind::Binary { op: BinaryOp::Add, .. }
+            )
+        ));
+        assert_eq!(script.eof, span(13, 13, 2, 2));
+    }
+}
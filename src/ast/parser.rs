@@ -0,0 +1,204 @@
+use super::{BinaryOp, Expr, ExprKind, Literal, Script, Span, UnaryOp};
+use crate::compiler::{Compiler, Parser, Precedence};
+use crate::scanner::{Scanner, Token, TokenType};
+
+/// Builds the tree with the same grammar, precedence table and error
+/// reporting as the single-pass compiler
+struct AstParser<'a> {
+    source: &'a str,
+    parser: Parser<'a>,
+}
+
+impl<'a> AstParser<'a> {
+    fn span(&self, token: Token<'a>) -> Span {
+        let start = token.slice.as_ptr() as usize - self.source.as_ptr() as usize;
+        Span {
+            start,
+            end: start + token.slice.len(),
+            line: token.line,
+            end_line: token.line,
+        }
+    }
+
+    fn previous_span(&self) -> Span {
+        self.span(self.parser.previous)
+    }
+
+    fn node(kind: ExprKind, span: Span) -> Option<Expr> {
+        Some(Expr { kind, span })
+    }
+
+    fn expression(&mut self) -> Option<Expr> {
+        self.parse_precedence(Precedence::Assignment)
+    }
+
+    fn parse_precedence(&mut self, precedence: Precedence) -> Option<Expr> {
+        self.parser.advance();
+        let mut expr = self.prefix()?;
+
+        while precedence <= Compiler::precedence_for(self.parser.current.token_type) {
+            self.parser.advance();
+            expr = self.infix(expr)?;
+        }
+        Some(expr)
+    }
+
+    fn prefix(&mut self) -> Option<Expr> {
+        let span = self.previous_span();
+        match self.parser.previous.token_type {
+            TokenType::LeftParen => {
+                let inner = self.expression()?;
+                self.parser
+                    .consume(TokenType::RightParen, "Expect ')' after expression.");
+                let span = span.to(self.previous_span());
+                Self::node(ExprKind::Grouping(Box::new(inner)), span)
+            }
+            TokenType::Bang | TokenType::Minus | TokenType::Tilde => {
+                let op = match self.parser.previous.token_type {
+                    TokenType::Bang => UnaryOp::Not,
+                    TokenType::Minus => UnaryOp::Negate,
+                    _ => UnaryOp::BitNot,
+                };
+                let operand = self.parse_precedence(Precedence::Unary)?;
+                let span = span.to(operand.span);
+                Self::node(
+                    ExprKind::Unary {
+                        op,
+                        operand: Box::new(operand),
+                    },
+                    span,
+                )
+            }
+            TokenType::Number => self.number(span),
+            TokenType::String => {
+                let slice = self.parser.previous.slice;
+                let contents = slice[1..slice.len() - 1].to_owned();
+                Self::node(ExprKind::Literal(Literal::String(contents)), span)
+            }
+            TokenType::False => Self::node(ExprKind::Literal(Literal::Bool(false)), span),
+            TokenType::True => Self::node(ExprKind::Literal(Literal::Bool(true)), span),
+            TokenType::Nil => Self::node(ExprKind::Literal(Literal::Nil), span),
+            _ => {
+                self.parser.error("Expect expression.");
+                None
+            }
+        }
+    }
+
+    fn number(&mut self, span: Span) -> Option<Expr> {
+        let slice = self.parser.previous.slice;
+        let literal = if slice.contains('.') {
+            Literal::Number(slice.parse().unwrap())
+        } else {
+            match slice.parse::<i64>() {
+                Ok(n) => Literal::Int(n),
+                Err(_) => {
+                    self.parser.error("Integer literal too large.");
+                    return None;
+                }
+            }
+        };
+        Self::node(ExprKind::Literal(literal), span)
+    }
+
+    fn infix(&mut self, left: Expr) -> Option<Expr> {
+        let operator_type = self.parser.previous.token_type;
+        let operator = self.previous_span();
+        let left = Box::new(left);
+
+        let kind = match operator_type {
+            TokenType::Question => {
+                let then_branch = Box::new(self.expression()?);
+                self.parser.consume(
+                    TokenType::Colon,
+                    "Expect ':' after then branch of conditional expression.",
+                );
+                let else_branch = Box::new(self.parse_precedence(Precedence::Conditional)?);
+                ExprKind::Conditional {
+                    condition: left,
+                    then_branch,
+                    else_branch,
+                    question: operator,
+                }
+            }
+            TokenType::QuestionQuestion => ExprKind::Coalesce {
+                left,
+                right: Box::new(self.parse_precedence(Precedence::Coalesce.incr())?),
+                operator,
+            },
+            // Exponentiation is right-associative and accepts a unary
+            // operator on its right
+            TokenType::StarStar => ExprKind::Binary {
+                op: BinaryOp::Power,
+                left,
+                right: Box::new(self.parse_precedence(Precedence::Unary)?),
+            },
+            _ => {
+                let op = binary_op(operator_type);
+                let precedence = Compiler::precedence_for(operator_type).incr();
+                ExprKind::Binary {
+                    op,
+                    left,
+                    right: Box::new(self.parse_precedence(precedence)?),
+                }
+            }
+        };
+
+        let span = match &kind {
+            ExprKind::Conditional {
+                condition,
+                else_branch,
+                ..
+            } => condition.span.to(else_branch.span),
+            ExprKind::Coalesce { left, right, .. } | ExprKind::Binary { left, right, .. } => {
+                left.span.to(right.span)
+            }
+            _ => unreachable!(),
+        };
+        Self::node(kind, span)
+    }
+}
+
+fn binary_op(token_type: TokenType) -> BinaryOp {
+    match token_type {
+        TokenType::BangEqual => BinaryOp::NotEqual,
+        TokenType::EqualEqual => BinaryOp::Equal,
+        TokenType::Greater => BinaryOp::Greater,
+        TokenType::GreaterEqual => BinaryOp::GreaterEqual,
+        TokenType::Less => BinaryOp::Less,
+        TokenType::LessEqual => BinaryOp::LessEqual,
+        TokenType::Plus => BinaryOp::Add,
+        TokenType::Minus => BinaryOp::Subtract,
+        TokenType::Star => BinaryOp::Multiply,
+        TokenType::Slash => BinaryOp::Divide,
+        TokenType::Backslash => BinaryOp::IntDivide,
+        TokenType::Percent => BinaryOp::Modulo,
+        TokenType::Ampersand => BinaryOp::BitAnd,
+        TokenType::Pipe => BinaryOp::BitOr,
+        TokenType::Caret => BinaryOp::BitXor,
+        TokenType::LessLess => BinaryOp::ShiftLeft,
+        TokenType::GreaterGreater => BinaryOp::ShiftRight,
+        _ => unreachable!(),
+    }
+}
+
+/// Parse a script, reporting errors as the compiler does. Returns None if
+/// there were any.
+pub fn parse(source: &str) -> Option<Script> {
+    let mut parser = AstParser {
+        source,
+        parser: Parser::new(Scanner::new(source)),
+    };
+
+    parser.parser.advance();
+    let body = parser.expression();
+    parser
+        .parser
+        .consume(TokenType::EOF, "Expect end of expression");
+
+    let eof = parser.previous_span();
+    match body {
+        Some(body) if !parser.parser.had_error => Some(Script { body, eof }),
+        _ => None,
+    }
+}
@@ -0,0 +1,250 @@
+use std::fmt::Display;
+
+use super::{Chunk, Instruction, OpCode};
+use crate::value::Value;
+use crate::verify::{verify, VerifyError};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BuildError {
+    TooManyConstants,
+    NotAJump(OpCode),
+    BackwardJump,
+    JumpTooFar,
+    LabelAlreadyBound,
+    UnboundLabel,
+    UnknownLabel,
+    Invalid(VerifyError),
+}
+
+impl Display for BuildError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::TooManyConstants => write!(f, "Too many constants in one chunk."),
+            Self::NotAJump(op) => write!(f, "{:?} is not a jump instruction.", op),
+            Self::BackwardJump => write!(f, "Jumps can only go forward."),
+            Self::JumpTooFar => write!(f, "Too much code to jump over."),
+            Self::LabelAlreadyBound => write!(f, "Label is already bound."),
+            Self::UnboundLabel => write!(f, "Jump to a label that was never bound."),
+            Self::UnknownLabel => write!(f, "Label belongs to another builder."),
+            Self::Invalid(error) => write!(f, "{}", error),
+        }
+    }
+}
+
+/// A position in the code that jumps can target before it is known
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Label(usize);
+
+/// Safe construction of chunks for tools that generate bytecode. Jumps go
+/// to labels and are patched once the label is bound, and the finished
+/// chunk is verified before it is handed back.
+#[derive(Default)]
+pub struct ChunkBuilder {
+    chunk: Chunk,
+    labels: Vec<Option<usize>>,
+    // Offsets of the operands of jumps waiting for their label
+    pending: Vec<(usize, Label)>,
+}
+
+impl ChunkBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The offset the next instruction will be written at
+    pub fn offset(&self) -> usize {
+        self.chunk.count
+    }
+
+    pub fn add_constant(&mut self, value: Value) -> Result<u8, BuildError> {
+        // Check before adding, so a failed call leaves the pool alone
+        let index =
+            u8::try_from(self.chunk.constants.count).map_err(|_| BuildError::TooManyConstants)?;
+        self.chunk.add_constant(value);
+        Ok(index)
+    }
+
+    pub fn emit(&mut self, instruction: Instruction, line: u32) {
+        instruction.write_to(&mut self.chunk, line);
+    }
+
+    pub fn new_label(&mut self) -> Label {
+        self.labels.push(None);
+        Label(self.labels.len() - 1)
+    }
+
+    /// Emit a jump of the given kind to a label that is bound later
+    pub fn jump(&mut self, op: OpCode, label: Label, line: u32) -> Result<(), BuildError> {
+        let instruction = match op {
+            OpCode::Jump => Instruction::Jump(0xffff),
+            OpCode::JumpIfFalse => Instruction::JumpIfFalse(0xffff),
+            OpCode::JumpIfNotNil => Instruction::JumpIfNotNil(0xffff),
+            OpCode::JumpIfFalsePop => Instruction::JumpIfFalsePop(0xffff),
+            _ => return Err(BuildError::NotAJump(op)),
+        };
+        if self.target(label)?.is_some() {
+            return Err(BuildError::BackwardJump);
+        }
+
+        self.emit(instruction, line);
+        self.pending.push((self.offset() - 2, label));
+        Ok(())
+    }
+
+    /// Bind a label to the current offset, patching the jumps that target it
+    pub fn bind(&mut self, label: Label) -> Result<(), BuildError> {
+        if self.target(label)?.is_some() {
+            return Err(BuildError::LabelAlreadyBound);
+        }
+        let target = self.offset();
+
+        // Work out every jump before changing anything, so a jump that's
+        // too far leaves the builder as it was
+        let patches = self
+            .pending
+            .iter()
+            .filter(|(_, l)| *l == label)
+            .map(|&(operand, _)| {
+                // -2 to adjust for the bytecode for the jump offset itself
+                let jump =
+                    u16::try_from(target - operand - 2).map_err(|_| BuildError::JumpTooFar)?;
+                Ok((operand, jump))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        self.labels[label.0] = Some(target);
+        self.pending.retain(|(_, l)| *l != label);
+        for (operand, jump) in patches {
+            let [high, low] = jump.to_be_bytes();
+            self.chunk.patch(operand, high);
+            self.chunk.patch(operand + 1, low);
+        }
+        Ok(())
+    }
+
+    /// Where a label is bound, if it is yet
+    fn target(&self, label: Label) -> Result<Option<usize>, BuildError> {
+        self.labels
+            .get(label.0)
+            .copied()
+            .ok_or(BuildError::UnknownLabel)
+    }
+
+    pub fn finish(self) -> Result<Chunk, BuildError> {
+        if !self.pending.is_empty() {
+            return Err(BuildError::UnboundLabel);
+        }
+        verify(&self.chunk).map_err(BuildError::Invalid)?;
+        Ok(self.chunk)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_build_conditional() {
+        let mut builder = ChunkBuilder::new();
+        let one = builder.add_constant(1.into()).unwrap();
+        let two = builder.add_constant(2.into()).unwrap();
+        let else_label = builder.new_label();
+        let end_label = builder.new_label();
+
+        builder.emit(Instruction::True, 1);
+        builder.jump(OpCode::JumpIfFalse, else_label, 1).unwrap();
+        builder.emit(Instruction::Pop, 1);
+        builder.emit(Instruction::Constant(one), 1);
+        builder.jump(OpCode::Jump, end_label, 1).unwrap();
+        builder.bind(else_label).unwrap();
+        builder.emit(Instruction::Pop, 2);
+        builder.emit(Instruction::Constant(two), 2);
+        builder.bind(end_label).unwrap();
+        builder.emit(Instruction::Return, 2);
+
+        let chunk = builder.finish().unwrap();
+        assert_eq!(chunk.constants(), &[1.into(), 2.into()]);
+        assert_eq!(chunk.lines(), &[1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 2, 2, 2, 2]);
+        assert_eq!(
+            chunk.instructions().collect::<Result<Vec<_>, _>>(),
+            Ok(vec![
+                Instruction::True,
+                Instruction::JumpIfFalse(6),
+                Instruction::Pop,
+                Instruction::Constant(0),
+                Instruction::Jump(3),
+                Instruction::Pop,
+                Instruction::Constant(1),
+                Instruction::Return,
+            ])
+        );
+    }
+
+    #[test]
+    fn test_build_errors() {
+        let mut builder = ChunkBuilder::new();
+        let label = builder.new_label();
+
+        assert_eq!(
+            builder.jump(OpCode::Pop, label, 1),
+            Err(BuildError::NotAJump(OpCode::Pop))
+        );
+        builder.emit(Instruction::Nil, 1);
+        builder.jump(OpCode::JumpIfNotNil, label, 1).unwrap();
+        builder.emit(Instruction::Return, 1);
+        assert_eq!(builder.finish().err(), Some(BuildError::UnboundLabel));
+
+        let mut builder = ChunkBuilder::new();
+        let label = builder.new_label();
+        builder.bind(label).unwrap();
+        assert_eq!(builder.bind(label), Err(BuildError::LabelAlreadyBound));
+        assert_eq!(
+            builder.jump(OpCode::Jump, label, 1),
+            Err(BuildError::BackwardJump)
+        );
+        builder.emit(Instruction::Return, 1);
+        assert_eq!(
+            builder.finish().err(),
+            Some(BuildError::Invalid(VerifyError::StackUnderflow {
+                offset: 0
+            }))
+        );
+
+        let mut builder = ChunkBuilder::new();
+        for i in 0..256 {
+            assert_eq!(builder.add_constant(Value::NIL), Ok(i as u8));
+        }
+        assert_eq!(
+            builder.add_constant(Value::NIL),
+            Err(BuildError::TooManyConstants)
+        );
+        assert_eq!(builder.chunk.constants.count, 256);
+
+        let mut other = ChunkBuilder::new();
+        other.new_label();
+        let foreign = other.new_label();
+        let mut builder = ChunkBuilder::new();
+        assert_eq!(
+            builder.jump(OpCode::Jump, foreign, 1),
+            Err(BuildError::UnknownLabel)
+        );
+        assert_eq!(builder.bind(foreign), Err(BuildError::UnknownLabel));
+    }
+
+    #[test]
+    fn test_jump_too_far_leaves_the_label_unbound() {
+        let mut builder = ChunkBuilder::new();
+        let label = builder.new_label();
+        builder.emit(Instruction::True, 1);
+        builder.jump(OpCode::JumpIfFalse, label, 1).unwrap();
+        for _ in 0..=u16::MAX {
+            builder.emit(Instruction::Nil, 1);
+        }
+        let code = builder.chunk.code().to_vec();
+
+        assert_eq!(builder.bind(label), Err(BuildError::JumpTooFar));
+        assert_eq!(builder.target(label), Ok(None));
+        assert_eq!(builder.pending, [(2, label)]);
+        assert_eq!(builder.chunk.code(), code);
+    }
+}
@@ -0,0 +1,178 @@
+use std::fmt::Display;
+
+use super::{Chunk, OpCode};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DecodeError {
+    InvalidOpcode { offset: usize, byte: u8 },
+    Truncated { offset: usize },
+}
+
+impl Display for DecodeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::InvalidOpcode { offset, byte } => {
+                write!(f, "Invalid opcode {} at offset {}.", byte, offset)
+            }
+            Self::Truncated { offset } => {
+                write!(f, "Missing operand for instruction at offset {}.", offset)
+            }
+        }
+    }
+}
+
+macro_rules! instructions {
+    (
+        simple: $($simple:ident),+;
+        byte: $($byte:ident),+;
+        short: $($short:ident),+
+    ) => {
+        /// A decoded instruction: an opcode together with its typed operands
+        #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+        pub enum Instruction {
+            $($simple,)+
+            $($byte(u8),)+
+            $($short(u16),)+
+        }
+
+        impl Instruction {
+            pub fn opcode(&self) -> OpCode {
+                match self {
+                    $(Self::$simple => OpCode::$simple,)+
+                    $(Self::$byte(_) => OpCode::$byte,)+
+                    $(Self::$short(_) => OpCode::$short,)+
+                }
+            }
+
+            /// Decode the instruction that starts at `offset`
+            pub fn decode(code: &[u8], offset: usize) -> Result<Self, DecodeError> {
+                let byte = *code.get(offset).ok_or(DecodeError::Truncated { offset })?;
+                let op = OpCode::try_from(byte)
+                    .map_err(|_| DecodeError::InvalidOpcode { offset, byte })?;
+                let operands = code
+                    .get(offset + 1..offset + 1 + op.operand_len())
+                    .ok_or(DecodeError::Truncated { offset })?;
+
+                Ok(match op {
+                    $(OpCode::$simple => Self::$simple,)+
+                    $(OpCode::$byte => Self::$byte(operands[0]),)+
+                    $(OpCode::$short => Self::$short(u16::from_be_bytes([operands[0], operands[1]])),)+
+                })
+            }
+
+            /// Append the encoded instruction to a chunk
+            pub fn write_to(&self, chunk: &mut Chunk, line: u32) {
+                chunk.write(self.opcode().into(), line);
+                match *self {
+                    $(Self::$simple => {})+
+                    $(Self::$byte(operand) => chunk.write(operand, line),)+
+                    $(Self::$short(operand) => {
+                        let [high, low] = operand.to_be_bytes();
+                        chunk.write(high, line);
+                        chunk.write(low, line);
+                    })+
+                }
+            }
+        }
+    };
+}
+
+instructions! {
+    simple: Nil, True, False, Equal, Greater, Less, NotEqual, GreaterEqual, LessEqual, Add,
+        Subtract, Multiply, Divide, IntDivide, Modulo, Power, BitAnd, BitOr, BitXor, ShiftLeft, ShiftRight,
+        Not, Negate, BitNot, Pop, Return;
+    byte: Constant, ConstantAdd;
+    short: Jump, JumpIfFalse, JumpIfNotNil, JumpIfFalsePop
+}
+
+impl Instruction {
+    /// The number of bytes the instruction takes up in the code
+    pub fn size(&self) -> usize {
+        1 + self.opcode().operand_len()
+    }
+}
+
+/// Iterator over the instructions in a chunk's code along with the offset
+/// and source line of each. Decoding stops after the first error.
+pub struct Decoder<'a> {
+    code: &'a [u8],
+    lines: &'a [u32],
+    offset: usize,
+}
+
+impl<'a> Decoder<'a> {
+    pub(super) fn new(code: &'a [u8], lines: &'a [u32]) -> Self {
+        Self {
+            code,
+            lines,
+            offset: 0,
+        }
+    }
+}
+
+impl Iterator for Decoder<'_> {
+    type Item = Result<(usize, u32, Instruction), DecodeError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let offset = self.offset;
+        if offset >= self.code.len() {
+            return None;
+        }
+
+        match Instruction::decode(self.code, offset) {
+            Ok(instruction) => {
+                self.offset += instruction.size();
+                Some(Ok((offset, self.lines[offset], instruction)))
+            }
+            Err(error) => {
+                self.offset = self.code.len();
+                Some(Err(error))
+            }
+        }
+    }
+}
+
+/// Iterator over just the instructions in a chunk's code
+pub struct Instructions<'a>(Decoder<'a>);
+
+impl<'a> Instructions<'a> {
+    pub(super) fn new(decoder: Decoder<'a>) -> Self {
+        Self(decoder)
+    }
+}
+
+impl Iterator for Instructions<'_> {
+    type Item = Result<Instruction, DecodeError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.0
+            .next()
+            .map(|result| result.map(|(_, _, instruction)| instruction))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_decode_errors() {
+        let code = [OpCode::Nil as u8, 0xfe, OpCode::Return as u8];
+        let mut instructions = Decoder::new(&code, &[3, 4, 4]);
+
+        assert_eq!(instructions.next(), Some(Ok((0, 3, Instruction::Nil))));
+        assert_eq!(
+            instructions.next(),
+            Some(Err(DecodeError::InvalidOpcode {
+                offset: 1,
+                byte: 0xfe
+            }))
+        );
+        assert_eq!(instructions.next(), None);
+
+        assert_eq!(
+            Instruction::decode(&[OpCode::Jump as u8, 0], 0),
+            Err(DecodeError::Truncated { offset: 0 })
+        );
+    }
+}
@@ -0,0 +1,222 @@
+use std::ops::Index;
+use std::{ptr, slice};
+
+use crate::memory::{free_array, grow_array, grow_capacity};
+use crate::value::{Value, ValueArray};
+
+mod builder;
+mod instruction;
+
+pub use builder::{BuildError, ChunkBuilder, Label};
+pub use instruction::{DecodeError, Decoder, Instruction, Instructions};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+#[non_exhaustive]
+pub enum OpCode {
+    Constant,
+    ConstantAdd,
+    Nil,
+    True,
+    False,
+    Equal,
+    Greater,
+    Less,
+    NotEqual,
+    GreaterEqual,
+    LessEqual,
+    Add,
+    Subtract,
+    Multiply,
+    Divide,
+    IntDivide,
+    Modulo,
+    Power,
+    BitAnd,
+    BitOr,
+    BitXor,
+    ShiftLeft,
+    ShiftRight,
+    Not,
+    Negate,
+    BitNot,
+    Pop,
+    Jump,
+    JumpIfFalse,
+    JumpIfNotNil,
+    JumpIfFalsePop,
+    Return,
+}
+
+impl OpCode {
+    #[inline]
+    pub(crate) unsafe fn from_byte(input: u8) -> Self {
+        std::mem::transmute(input)
+    }
+}
+
+impl OpCode {
+    /// The number of operand bytes that follow this opcode in the code
+    pub(crate) fn operand_len(self) -> usize {
+        match self {
+            Self::Constant | Self::ConstantAdd => 1,
+            _ if self.is_jump() => 2,
+            _ => 0,
+        }
+    }
+
+    pub(crate) fn is_jump(self) -> bool {
+        matches!(
+            self,
+            Self::Jump | Self::JumpIfFalse | Self::JumpIfNotNil | Self::JumpIfFalsePop
+        )
+    }
+}
+
+impl From<OpCode> for u8 {
+    fn from(code: OpCode) -> Self {
+        code as Self
+    }
+}
+
+impl TryFrom<u8> for OpCode {
+    type Error = ();
+
+    fn try_from(op: u8) -> Result<Self, Self::Error> {
+        if op >= (OpCode::Constant as u8) && op < ((OpCode::Return as u8) + 1) {
+            // We know that it's a valid Opcode here so we can transmute
+            Ok(unsafe { std::mem::transmute::<u8, OpCode>(op) })
+        } else {
+            Err(())
+        }
+    }
+}
+
+pub struct Chunk {
+    pub(crate) code: *mut u8,
+    pub(crate) lines: *mut u32,
+    pub(crate) count: usize,
+    capacity: usize,
+    pub(crate) constants: ValueArray,
+}
+
+impl Chunk {
+    pub fn new() -> Self {
+        Self {
+            count: 0,
+            capacity: 0,
+            code: ptr::null_mut(),
+            lines: ptr::null_mut(),
+            constants: ValueArray::new(),
+        }
+    }
+
+    pub fn write(&mut self, byte: u8, line: u32) {
+        if self.capacity < self.count + 1 {
+            let old_capacity = self.capacity;
+            self.capacity = grow_capacity(old_capacity);
+            self.code = grow_array(self.code, old_capacity, self.capacity);
+            self.lines = grow_array(self.lines, old_capacity, self.capacity);
+        }
+
+        unsafe {
+            ptr::write(self.code.add(self.count), byte);
+            ptr::write(self.lines.add(self.count), line);
+        }
+        self.count += 1;
+    }
+
+    /// Overwrite a byte that has already been written, such as a jump
+    /// operand once its target is known
+    pub(crate) fn patch(&mut self, offset: usize, byte: u8) {
+        assert!(offset < self.count, "patch past the end of the code");
+        unsafe {
+            ptr::write(self.code.add(offset), byte);
+        }
+    }
+
+    /// Drop the code from `count` onwards
+    pub(crate) fn truncate(&mut self, count: usize) {
+        self.count = self.count.min(count);
+    }
+
+    pub fn add_constant(&mut self, value: Value) -> usize {
+        self.constants.write(value);
+        self.constants.count - 1
+    }
+
+    pub fn line(&self, offset: usize) -> u32 {
+        self.lines()[offset]
+    }
+
+    pub fn code(&self) -> &[u8] {
+        if self.count == 0 {
+            return &[];
+        }
+        unsafe { slice::from_raw_parts(self.code, self.count) }
+    }
+
+    pub fn lines(&self) -> &[u32] {
+        if self.count == 0 {
+            return &[];
+        }
+        unsafe { slice::from_raw_parts(self.lines, self.count) }
+    }
+
+    pub fn constants(&self) -> &[Value] {
+        self.constants.as_slice()
+    }
+
+    pub fn instructions(&self) -> Instructions<'_> {
+        Instructions::new(self.decode())
+    }
+
+    /// Decode the code into `(offset, line, instruction)` triples
+    pub fn decode(&self) -> Decoder<'_> {
+        Decoder::new(self.code(), self.lines())
+    }
+}
+
+impl Default for Chunk {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Index<usize> for Chunk {
+    type Output = u8;
+    fn index(&self, index: usize) -> &Self::Output {
+        &self.code()[index]
+    }
+}
+
+impl Drop for Chunk {
+    fn drop(&mut self) {
+        free_array(self.code, self.capacity);
+        free_array(self.lines, self.capacity);
+        // We don't zero the fields because Rust won't let us use this
+        // after it's freed
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_patch() {
+        let mut chunk = Chunk::new();
+        chunk.write(OpCode::Nil as u8, 1);
+        chunk.write(OpCode::Return as u8, 1);
+        chunk.patch(0, OpCode::True as u8);
+        assert_eq!(chunk.code(), [OpCode::True as u8, OpCode::Return as u8]);
+    }
+
+    #[test]
+    #[should_panic(expected = "patch past the end of the code")]
+    fn test_patch_past_the_end() {
+        let mut chunk = Chunk::new();
+        chunk.write(OpCode::Return as u8, 1);
+        chunk.patch(1, 0);
+    }
+}
@@ -1,33 +1,38 @@
 use crate::chunk::{Chunk, OpCode};
+use crate::fold;
 use crate::object::Obj;
 use crate::scanner::{Scanner, Token, TokenType};
 use crate::value::Value;
+use crate::verify::verify;
 use std::mem;
 
-#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Hash)]
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq, PartialOrd, Hash)]
 #[repr(u8)]
-enum Precedence {
+#[allow(dead_code)]
+pub(crate) enum Precedence {
+    #[default]
     None,
     Assignment,
+    Conditional,
+    Coalesce,
     Or,
     And,
     Equality,
     Comparison,
+    BitOr,
+    BitXor,
+    BitAnd,
+    Shift,
     Term,
     Factor,
     Unary,
+    Exponent,
     Call,
     Primary,
 }
 
-impl Default for Precedence {
-    fn default() -> Self {
-        Self::None
-    }
-}
-
 impl Precedence {
-    fn incr(self) -> Self {
+    pub(crate) fn incr(self) -> Self {
         if self == Self::Primary {
             panic!("Can't increment primary precedence")
         }
@@ -35,16 +40,16 @@ impl Precedence {
     }
 }
 
-struct Parser<'a> {
+pub(crate) struct Parser<'a> {
     scanner: Scanner<'a>,
-    current: Token<'a>,
-    previous: Token<'a>,
-    had_error: bool,
+    pub(crate) current: Token<'a>,
+    pub(crate) previous: Token<'a>,
+    pub(crate) had_error: bool,
     panic_mode: bool,
 }
 
 impl<'a> Parser<'a> {
-    fn new(scanner: Scanner<'a>) -> Self {
+    pub(crate) fn new(scanner: Scanner<'a>) -> Self {
         Self {
             scanner,
             current: Default::default(),
@@ -54,7 +59,7 @@ impl<'a> Parser<'a> {
         }
     }
 
-    fn advance(&mut self) {
+    pub(crate) fn advance(&mut self) {
         self.previous = self.current;
 
         loop {
@@ -68,7 +73,7 @@ impl<'a> Parser<'a> {
         }
     }
 
-    fn consume(&mut self, token_type: TokenType, message: &str) {
+    pub(crate) fn consume(&mut self, token_type: TokenType, message: &str) {
         if self.current.token_type == token_type {
             self.advance();
             return;
@@ -77,11 +82,11 @@ impl<'a> Parser<'a> {
         self.error_at_current(message);
     }
 
-    fn error_at_current(&mut self, message: &str) {
+    pub(crate) fn error_at_current(&mut self, message: &str) {
         self.error_at(self.current, message);
     }
 
-    fn error(&mut self, message: &str) {
+    pub(crate) fn error(&mut self, message: &str) {
         self.error_at(self.previous, message);
     }
 
@@ -105,13 +110,20 @@ impl<'a> Parser<'a> {
     }
 }
 
+// A `_` prefix marks a token that can't start an expression
 macro_rules! rule_lookups {
-    ($($token_type:ident, $prefix:ident, $infix:ident, $precedence:ident);+) =>{
+    (@prefix $self:ident, _) => { false };
+    (@prefix $self:ident, $prefix:ident) => {{ $self.$prefix(); true }};
+    ($($token_type:ident, $prefix:tt, $infix:ident, $precedence:ident);+) =>{
         fn prefix_parser(&mut self, token_type: TokenType) -> bool {
-            match token_type {
-                $(TokenType::$token_type => { self.$prefix(); true }),+,
-                _ => {self.parser.error("Expect expression."); false}
+            let parsed = match token_type {
+                $(TokenType::$token_type => rule_lookups!(@prefix self, $prefix)),+,
+                _ => false,
+            };
+            if !parsed {
+                self.parser.error("Expect expression.");
             }
+            parsed
         }
 
         fn infix_parser(&mut self, token_type: TokenType) {
@@ -121,7 +133,7 @@ macro_rules! rule_lookups {
             }
         }
 
-        fn precedence_for(&self, token_type: TokenType) -> Precedence {
+        pub(crate) fn precedence_for(token_type: TokenType) -> Precedence {
             match token_type {
                 $(TokenType::$token_type => Precedence::$precedence),+,
                 _ => Precedence::None,
@@ -139,29 +151,60 @@ macro_rules! opcode {
     };
 }
 
-struct Compiler<'a> {
+/// An instruction that pushes a known value, candidate for folding
+#[derive(Clone, Copy)]
+struct Literal {
+    start: usize,
+    end: usize,
+    value: Value,
+    // Where the value sits in the constant pool, if it was loaded from there
+    constant: Option<usize>,
+}
+
+pub(crate) struct Compiler<'a> {
     parser: Parser<'a>,
     chunk: &'a mut Chunk,
+    // Recently emitted literals, most recent last. Entries can be stale;
+    // only ones that sit directly before the end of the code are used.
+    literals: Vec<Literal>,
+    // Folding must not swallow an instruction that a jump lands on
+    last_jump_target: usize,
 }
 
 impl<'a> Compiler<'a> {
     fn new(parser: Parser<'a>, chunk: &'a mut Chunk) -> Self {
-        Self { parser, chunk }
+        Self {
+            parser,
+            chunk,
+            literals: Vec::new(),
+            last_jump_target: 0,
+        }
     }
 
     rule_lookups! {
         LeftParen, grouping, noop, None;
+        Question, _, conditional, Conditional;
+        QuestionQuestion, _, coalesce, Coalesce;
         Minus, unary, binary, Term;
-        Plus, boom, binary, Term;
-        Slash, boom, binary, Factor;
-        Star, boom, binary, Factor;
+        Plus, _, binary, Term;
+        Slash, _, binary, Factor;
+        Star, _, binary, Factor;
+        Percent, _, binary, Factor;
+        Backslash, _, binary, Factor;
+        StarStar, _, exponent, Exponent;
+        Ampersand, _, binary, BitAnd;
+        Pipe, _, binary, BitOr;
+        Caret, _, binary, BitXor;
+        LessLess, _, binary, Shift;
+        GreaterGreater, _, binary, Shift;
+        Tilde, unary, noop, None;
         Bang, unary, noop, None;
-        BangEqual, boom, binary, Equality;
-        EqualEqual, boom, binary, Equality;
-        Greater, boom, binary, Comparison;
-        GreaterEqual, boom, binary, Comparison;
-        Less, boom, binary, Comparison;
-        LessEqual, boom, binary, Comparison;
+        BangEqual, _, binary, Equality;
+        EqualEqual, _, binary, Equality;
+        Greater, _, binary, Comparison;
+        GreaterEqual, _, binary, Comparison;
+        Less, _, binary, Comparison;
+        LessEqual, _, binary, Comparison;
         String, string, noop, None;
         Number, number, noop, None;
         False, literal, noop, None;
@@ -171,10 +214,6 @@ impl<'a> Compiler<'a> {
 
     fn noop(&mut self) {}
 
-    fn boom(&mut self) {
-        panic!("boom");
-    }
-
     fn emit_byte(&mut self, byte: u8) {
         self.chunk.write(byte, self.parser.previous.line);
     }
@@ -184,12 +223,41 @@ impl<'a> Compiler<'a> {
         self.emit_byte(byte2);
     }
 
+    fn emit_jump(&mut self, instruction: OpCode) -> usize {
+        self.emit_byte(instruction as u8);
+        self.emit_bytes(0xff, 0xff);
+        self.chunk.count - 2
+    }
+
+    fn patch_jump(&mut self, offset: usize) {
+        // -2 to adjust for the bytecode for the jump offset itself
+        let jump = self.chunk.count - offset - 2;
+        self.last_jump_target = self.chunk.count;
+
+        match u16::try_from(jump) {
+            Ok(jump) => {
+                let [high, low] = jump.to_be_bytes();
+                self.chunk.patch(offset, high);
+                self.chunk.patch(offset + 1, low);
+            }
+            Err(_) => self.parser.error("Too much code to jump over."),
+        }
+    }
+
     fn end_compiler(&mut self) {
         self.emit_byte(OpCode::Return as u8);
     }
 
     fn number(&mut self) {
-        self.emit_constant(self.parser.previous.slice.parse::<f64>().unwrap().into())
+        let slice = self.parser.previous.slice;
+        if slice.contains('.') {
+            self.emit_constant(slice.parse::<f64>().unwrap().into())
+        } else {
+            match slice.parse::<i64>() {
+                Ok(n) => self.emit_constant(n.into()),
+                Err(_) => self.parser.error("Integer literal too large."),
+            }
+        }
     }
 
     fn string(&mut self) {
@@ -197,8 +265,82 @@ impl<'a> Compiler<'a> {
     }
 
     fn emit_constant(&mut self, value: Value) {
+        let start = self.chunk.count;
         let constant = self.make_constant(value);
-        self.emit_bytes(OpCode::Constant as u8, constant)
+        self.emit_bytes(OpCode::Constant as u8, constant);
+        self.literals.push(Literal {
+            start,
+            end: self.chunk.count,
+            value,
+            constant: Some(self.chunk.constants.count - 1),
+        });
+    }
+
+    /// Emit the shortest instruction that pushes a known value
+    fn emit_value(&mut self, value: Value) {
+        let start = self.chunk.count;
+        if value.is_nil() {
+            opcode!(self, Nil);
+        } else if value.is_bool() && value.as_bool() {
+            opcode!(self, True);
+        } else if value.is_bool() {
+            opcode!(self, False);
+        } else {
+            return self.emit_constant(value);
+        }
+        self.push_literal(start, value);
+    }
+
+    fn push_literal(&mut self, start: usize, value: Value) {
+        self.literals.push(Literal {
+            start,
+            end: self.chunk.count,
+            value,
+            constant: None,
+        });
+    }
+
+    /// The last `count` literals, if they are the last instructions in the
+    /// code and no jump lands inside them
+    fn trailing_literals(&self, count: usize) -> Option<&[Literal]> {
+        let literals = self
+            .literals
+            .get(self.literals.len().checked_sub(count)?..)?;
+        let mut end = self.chunk.count;
+        for literal in literals.iter().rev() {
+            if literal.end != end {
+                return None;
+            }
+            end = literal.start;
+        }
+        (end >= self.last_jump_target).then_some(literals)
+    }
+
+    /// Emit an operator, or evaluate it now if its operands are literals.
+    /// Operations that would fail are left for the VM to report.
+    fn emit_operator(&mut self, op: OpCode) {
+        let arity = if fold::is_unary(op) { 1 } else { 2 };
+        let folded = self
+            .trailing_literals(arity)
+            .and_then(|operands| match operands {
+                [a] => fold::unary(op, a.value),
+                [a, b] => fold::binary(op, a.value, b.value),
+                _ => None,
+            });
+        let Some(value) = folded else {
+            return self.emit_byte(op as u8);
+        };
+
+        let start = self.literals[self.literals.len() - arity].start;
+        self.chunk.truncate(start);
+        for _ in 0..arity {
+            let operand = self.literals.pop().unwrap();
+            // Constants added after the operand's are still referenced
+            if operand.constant.map(|c| c + 1) == Some(self.chunk.constants.count) {
+                self.chunk.constants.pop();
+            }
+        }
+        self.emit_value(value);
     }
 
     fn make_constant(&mut self, value: Value) -> u8 {
@@ -223,39 +365,77 @@ impl<'a> Compiler<'a> {
 
         self.parse_precedence(Precedence::Unary);
 
-        match operator_type {
-            TokenType::Bang => self.emit_byte(OpCode::Not as u8),
-            TokenType::Minus => self.emit_byte(OpCode::Negate as u8),
+        self.emit_operator(match operator_type {
+            TokenType::Bang => OpCode::Not,
+            TokenType::Minus => OpCode::Negate,
+            TokenType::Tilde => OpCode::BitNot,
             _ => unreachable!(),
-        }
+        })
     }
 
     fn binary(&mut self) {
         let operator_type = self.parser.previous.token_type;
-        self.parse_precedence(self.precedence_for(operator_type).incr());
-
-        match operator_type {
-            TokenType::BangEqual => opcode!(self, Equal, Not),
-            TokenType::EqualEqual => opcode!(self, Equal),
-            TokenType::Greater => opcode!(self, Greater),
-            TokenType::GreaterEqual => opcode!(self, Less, Not),
-            TokenType::Less => opcode!(self, Less),
-            TokenType::LessEqual => opcode!(self, Greater, Not),
-            TokenType::Plus => opcode!(self, Add),
-            TokenType::Minus => opcode!(self, Subtract),
-            TokenType::Star => opcode!(self, Multiply),
-            TokenType::Slash => opcode!(self, Divide),
+        self.parse_precedence(Self::precedence_for(operator_type).incr());
+
+        self.emit_operator(match operator_type {
+            TokenType::BangEqual => OpCode::NotEqual,
+            TokenType::EqualEqual => OpCode::Equal,
+            TokenType::Greater => OpCode::Greater,
+            TokenType::GreaterEqual => OpCode::GreaterEqual,
+            TokenType::Less => OpCode::Less,
+            TokenType::LessEqual => OpCode::LessEqual,
+            TokenType::Plus => OpCode::Add,
+            TokenType::Minus => OpCode::Subtract,
+            TokenType::Star => OpCode::Multiply,
+            TokenType::Slash => OpCode::Divide,
+            TokenType::Backslash => OpCode::IntDivide,
+            TokenType::Percent => OpCode::Modulo,
+            TokenType::Ampersand => OpCode::BitAnd,
+            TokenType::Pipe => OpCode::BitOr,
+            TokenType::Caret => OpCode::BitXor,
+            TokenType::LessLess => OpCode::ShiftLeft,
+            TokenType::GreaterGreater => OpCode::ShiftRight,
             _ => unreachable!(),
-        };
+        });
+    }
+
+    fn exponent(&mut self) {
+        // Exponentiation is right-associative and binds tighter than a
+        // unary operator on its left, but still accepts one on its right
+        self.parse_precedence(Precedence::Unary);
+        self.emit_operator(OpCode::Power);
+    }
+
+    fn conditional(&mut self) {
+        let else_jump = self.emit_jump(OpCode::JumpIfFalse);
+        opcode!(self, Pop);
+        self.expression();
+        let end_jump = self.emit_jump(OpCode::Jump);
+
+        self.patch_jump(else_jump);
+        opcode!(self, Pop);
+        self.parser.consume(
+            TokenType::Colon,
+            "Expect ':' after then branch of conditional expression.",
+        );
+        self.parse_precedence(Precedence::Conditional);
+        self.patch_jump(end_jump);
+    }
+
+    fn coalesce(&mut self) {
+        let end_jump = self.emit_jump(OpCode::JumpIfNotNil);
+        opcode!(self, Pop);
+        self.parse_precedence(Precedence::Coalesce.incr());
+        self.patch_jump(end_jump);
     }
 
     fn literal(&mut self) {
-        self.emit_byte(match self.parser.previous.token_type {
-            TokenType::False => OpCode::False,
-            TokenType::Nil => OpCode::Nil,
-            TokenType::True => OpCode::True,
+        self.emit_value(match self.parser.previous.token_type {
+            TokenType::False => false.into(),
+            TokenType::Nil => Value::NIL,
+            TokenType::True => true.into(),
             _ => unreachable!(),
-        } as u8)
+        })
     }
 
     fn expression(&mut self) {
@@ -269,7 +449,7 @@ impl<'a> Compiler<'a> {
             return;
         }
 
-        while precedence <= self.precedence_for(self.parser.current.token_type) {
+        while precedence <= Self::precedence_for(self.parser.current.token_type) {
             self.parser.advance();
             self.infix_parser(self.parser.previous.token_type)
         }
@@ -288,5 +468,18 @@ pub fn compile(source: &str, chunk: &mut Chunk) -> bool {
         .consume(TokenType::EOF, "Expect end of expression");
 
     compiler.end_compiler();
-    !compiler.parser.had_error
+    if compiler.parser.had_error {
+        return false;
+    }
+
+    // Well-formed source can still be too deeply nested for the stack,
+    // which only verifying the chunk catches
+    let line = compiler.parser.current.line;
+    match verify(chunk) {
+        Ok(()) => true,
+        Err(error) => {
+            eprintln!("[line {}] Error: {}", line, error);
+            false
+        }
+    }
 }
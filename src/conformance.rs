@@ -0,0 +1,144 @@
+//! Programs with known results that every way of running source has to
+//! agree on: the stack VM at each optimization level and the register VM.
+
+use crate::optimizer::OptLevel;
+use crate::register::RegisterVM;
+use crate::testing::Shared;
+use crate::vm::{InterpretResult, VM};
+
+#[derive(Debug, PartialEq)]
+enum Outcome {
+    Prints(&'static str),
+    RuntimeError,
+    CompileError,
+}
+
+use Outcome::*;
+
+const CASES: &[(&str, Outcome)] = &[
+    // Literals
+    ("nil", Prints("nil")),
+    ("true", Prints("true")),
+    ("42", Prints("42")),
+    ("2.5", Prints("2.5")),
+    ("\"hi\"", Prints("\"hi\"")),
+    // Arithmetic, with operands that aren't folded
+    ("(nil ?? 1) + 2", Prints("3")),
+    ("(nil ?? 1.5) + 2", Prints("3.5")),
+    ("(nil ?? 7) - 10", Prints("-3")),
+    ("(nil ?? 6) * 7", Prints("42")),
+    ("(nil ?? 7) / 2", Prints("3.5")),
+    ("(nil ?? 7) \\ 2", Prints("3")),
+    ("(nil ?? -7) \\ 2", Prints("-3")),
+    ("(nil ?? 7) % 3", Prints("1")),
+    ("(nil ?? -7.5) % 2", Prints("-1.5")),
+    ("(nil ?? 2) ** 10", Prints("1024")),
+    ("(nil ?? 140737488355327) + 1", Prints("140737488355328")),
+    ("(nil ?? -140737488355328) - 1", Prints("-140737488355329")),
+    ("(nil ?? 2) ** 62", Prints("4611686018427387904")),
+    (
+        "(nil ?? 9223372036854775807) \\ 7",
+        Prints("1317624576693539401"),
+    ),
+    ("(nil ?? 2) ** -1", Prints("0.5")),
+    ("-(nil ?? 3)", Prints("-3")),
+    ("-(nil ?? 0.5)", Prints("-0.5")),
+    ("(nil ?? 0.0) / 0 == (nil ?? 0.0) / 0", Prints("false")),
+    ("(nil ?? \"a\") + \"b\"", Prints("\"ab\"")),
+    ("\"a\" + \"b\" + (nil ?? \"c\")", Prints("\"abc\"")),
+    // Bitwise
+    ("(nil ?? 12) & 10", Prints("8")),
+    ("(nil ?? 12) | 3", Prints("15")),
+    ("(nil ?? 12) ^ 5", Prints("9")),
+    ("(nil ?? 1) << 4", Prints("16")),
+    ("(nil ?? 1) << 47", Prints("140737488355328")),
+    ("(nil ?? 1) << 63", Prints("-9223372036854775808")),
+    ("(nil ?? -16) >> 2", Prints("-4")),
+    ("~(nil ?? 0)", Prints("-1")),
+    // Comparison and logic
+    ("(nil ?? 1) < 2", Prints("true")),
+    ("(nil ?? 1) <= 1.0", Prints("true")),
+    ("(nil ?? 2) > 2", Prints("false")),
+    ("(nil ?? 2) >= 2.5", Prints("false")),
+    ("(nil ?? 1) == 1.0", Prints("true")),
+    (
+        "(nil ?? 9007199254740993) == 9007199254740992.0",
+        Prints("false"),
+    ),
+    (
+        "(nil ?? 9007199254740993) > 9007199254740992.0",
+        Prints("true"),
+    ),
+    (
+        "(nil ?? 9223372036854775807) < 9223372036854775807.0",
+        Prints("true"),
+    ),
+    ("(nil ?? -1) > -1.5", Prints("true")),
+    ("(nil ?? 1.0) / 0 > 9223372036854775807", Prints("true")),
+    ("(nil ?? \"a\") != \"a\"", Prints("false")),
+    ("!(nil ?? 0)", Prints("false")),
+    ("!(nil ?? nil)", Prints("true")),
+    // Control flow
+    ("(nil ?? 1) < 2 ? \"yes\" : \"no\"", Prints("\"yes\"")),
+    (
+        "(nil ?? 0) ? \"zero is truthy\" : \"no\"",
+        Prints("\"zero is truthy\""),
+    ),
+    ("(nil ?? false) ? 1 : (nil ?? nil) ?? 2", Prints("2")),
+    ("true ? false ? 1 : 2 : 3", Prints("2")),
+    ("nil ?? false ?? 3", Prints("false")),
+    ("(nil ?? 1) ?\n  -\"x\" :\n  2", RuntimeError),
+    // Runtime errors
+    ("-(nil ?? \"x\")", RuntimeError),
+    ("~(nil ?? 1.5)", RuntimeError),
+    ("(nil ?? 1) + \"a\"", RuntimeError),
+    ("(nil ?? \"a\") - \"b\"", RuntimeError),
+    ("(nil ?? 1) < \"a\"", RuntimeError),
+    ("(nil ?? 1) % 0", RuntimeError),
+    ("(nil ?? 1) \\ 0", RuntimeError),
+    ("(nil ?? 7.0) \\ 2", RuntimeError),
+    ("(nil ?? 3037000500) * 3037000500", RuntimeError),
+    ("(nil ?? 2) ** 64", RuntimeError),
+    ("(nil ?? 1) << 64", RuntimeError),
+    ("(nil ?? 1) << -1", RuntimeError),
+    ("(nil ?? 1.0) & 1", RuntimeError),
+    // Compile errors
+    ("", CompileError),
+    ("1 +", CompileError),
+    ("(1", CompileError),
+    ("1 ? 2", CompileError),
+    ("99999999999999999999", CompileError),
+];
+
+fn check(name: &str, mut run: impl FnMut(&str, Shared) -> InterpretResult) {
+    for (source, expected) in CASES {
+        let out = Shared::default();
+        let outcome = match run(source, out.clone()) {
+            InterpretResult::Ok => Prints(out.take().trim_end().to_owned().leak()),
+            InterpretResult::RuntimeError => RuntimeError,
+            InterpretResult::CompileError => CompileError,
+        };
+        assert_eq!(&outcome, expected, "{} running {:?}", name, source);
+    }
+}
+
+#[test]
+fn test_stack_vm() {
+    for level in [OptLevel::O0, OptLevel::O1] {
+        check(&format!("stack VM at {:?}", level), |source, out| {
+            let mut vm = VM::new();
+            vm.set_opt_level(level);
+            vm.set_output(out);
+            vm.interpret(source)
+        });
+    }
+}
+
+#[test]
+fn test_register_vm() {
+    check("register VM", |source, out| {
+        let mut vm = RegisterVM::new();
+        vm.set_output(out);
+        vm.interpret(source)
+    });
+}
@@ -1,55 +1,361 @@
-use crate::chunk::{Chunk, OpCode};
+use std::collections::BTreeMap;
+use std::fmt::{self, Write};
+use std::io;
+
+use crate::chunk::{Chunk, DecodeError, Instruction, OpCode};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    Text,
+    Json,
+}
+
+pub fn opcode_name(op: OpCode) -> &'static str {
+    match op {
+        OpCode::Constant => "OP_CONSTANT",
+        OpCode::ConstantAdd => "OP_CONSTANT_ADD",
+        OpCode::Nil => "OP_NIL",
+        OpCode::True => "OP_TRUE",
+        OpCode::False => "OP_FALSE",
+        OpCode::Equal => "OP_EQUAL",
+        OpCode::Greater => "OP_GREATER",
+        OpCode::Less => "OP_LESS",
+        OpCode::NotEqual => "OP_NOT_EQUAL",
+        OpCode::GreaterEqual => "OP_GREATER_EQUAL",
+        OpCode::LessEqual => "OP_LESS_EQUAL",
+        OpCode::Add => "OP_ADD",
+        OpCode::Subtract => "OP_SUBTRACT",
+        OpCode::Multiply => "OP_MULTIPLY",
+        OpCode::Divide => "OP_DIVIDE",
+        OpCode::IntDivide => "OP_INT_DIVIDE",
+        OpCode::Modulo => "OP_MODULO",
+        OpCode::Power => "OP_POWER",
+        OpCode::BitAnd => "OP_BIT_AND",
+        OpCode::BitOr => "OP_BIT_OR",
+        OpCode::BitXor => "OP_BIT_XOR",
+        OpCode::ShiftLeft => "OP_SHIFT_LEFT",
+        OpCode::ShiftRight => "OP_SHIFT_RIGHT",
+        OpCode::Not => "OP_NOT",
+        OpCode::Negate => "OP_NEGATE",
+        OpCode::BitNot => "OP_BIT_NOT",
+        OpCode::Pop => "OP_POP",
+        OpCode::Jump => "OP_JUMP",
+        OpCode::JumpIfFalse => "OP_JUMP_IF_FALSE",
+        OpCode::JumpIfNotNil => "OP_JUMP_IF_NOT_NIL",
+        OpCode::JumpIfFalsePop => "OP_JUMP_IF_FALSE_POP",
+        OpCode::Return => "OP_RETURN",
+    }
+}
+
+/// The offset a jump instruction at `offset` lands on
+pub fn jump_target(offset: usize, instruction: Instruction) -> Option<usize> {
+    match instruction {
+        Instruction::Jump(jump)
+        | Instruction::JumpIfFalse(jump)
+        | Instruction::JumpIfNotNil(jump)
+        | Instruction::JumpIfFalsePop(jump) => Some(offset + instruction.size() + jump as usize),
+        _ => None,
+    }
+}
 
 pub fn disassemble_chunk(chunk: &Chunk, name: &str) {
-    println!("== {} ==", name);
+    let mut out = String::new();
+    write_chunk(&mut out, chunk, name).unwrap();
+    print!("{}", out);
+}
+
+pub fn disassemble_instruction(chunk: &Chunk, offset: usize) -> usize {
+    let mut out = String::new();
+    let next = write_instruction(&mut out, chunk, offset).unwrap();
+    print!("{}", out);
+    next
+}
+
+/// Disassemble a chunk into any `io::Write` in the given format
+pub fn disassemble_to<W: io::Write>(
+    out: &mut W,
+    chunk: &Chunk,
+    name: &str,
+    format: Format,
+) -> io::Result<()> {
+    let mut text = String::new();
+    match format {
+        Format::Text => write_chunk(&mut text, chunk, name),
+        Format::Json => write_chunk_json(&mut text, chunk, name),
+    }
+    .unwrap();
+    out.write_all(text.as_bytes())
+}
+
+pub fn write_chunk<W: Write>(out: &mut W, chunk: &Chunk, name: &str) -> fmt::Result {
+    writeln!(out, "== {} ==", name)?;
 
     let mut offset = 0;
     while offset < chunk.count {
-        offset = disassemble_instruction(chunk, offset);
+        offset = write_instruction(out, chunk, offset)?;
     }
+    Ok(())
 }
 
-pub fn disassemble_instruction(chunk: &Chunk, offset: usize) -> usize {
-    print!("{:04} ", offset);
+pub fn write_instruction<W: Write>(
+    out: &mut W,
+    chunk: &Chunk,
+    offset: usize,
+) -> Result<usize, fmt::Error> {
+    write!(out, "{:04} ", offset)?;
     if offset > 0 && chunk.line(offset) == chunk.line(offset - 1) {
-        print!("   | ");
+        write!(out, "   | ")?;
     } else {
-        print!("{:4} ", chunk.line(offset));
-    }
-
-    let instruction = chunk[offset];
-    match OpCode::try_from(instruction) {
-        Ok(OpCode::Constant) => constant_instruction("OP_CONSTANT", chunk, offset),
-        Ok(OpCode::Nil) => simple_instruction("OP_NIL", offset),
-        Ok(OpCode::True) => simple_instruction("OP_TRUE", offset),
-        Ok(OpCode::False) => simple_instruction("OP_FALSE", offset),
-        Ok(OpCode::Equal) => simple_instruction("OP_EQUAL", offset),
-        Ok(OpCode::Greater) => simple_instruction("OP_GREATER", offset),
-        Ok(OpCode::Less) => simple_instruction("OP_FALSE", offset),
-        Ok(OpCode::Add) => simple_instruction("OP_ADD", offset),
-        Ok(OpCode::Subtract) => simple_instruction("OP_SUBTRACT", offset),
-        Ok(OpCode::Multiply) => simple_instruction("OP_MULTIPLY", offset),
-        Ok(OpCode::Divide) => simple_instruction("OP_DIVIDE", offset),
-        Ok(OpCode::Not) => simple_instruction("OP_NOT", offset),
-        Ok(OpCode::Negate) => simple_instruction("OP_NEGATE", offset),
-        Ok(OpCode::Return) => simple_instruction("OP_RETURN", offset),
-        Err(()) => {
-            println!("Unknown opcode {}", instruction);
-            offset + 1
-        }
-    }
-}
-
-fn simple_instruction(name: &str, offset: usize) -> usize {
-    println!("{}", name);
-    offset + 1
-}
-
-fn constant_instruction(name: &str, chunk: &Chunk, offset: usize) -> usize {
-    let constant = chunk[offset + 1];
-    println!(
-        "{:16} {:4} '{}'",
-        name, constant, chunk.constants[constant as usize]
-    );
-    offset + 2
+        write!(out, "{:4} ", chunk.line(offset))?;
+    }
+
+    let instruction = match Instruction::decode(chunk.code(), offset) {
+        Ok(instruction) => instruction,
+        Err(DecodeError::InvalidOpcode { byte, .. }) => {
+            writeln!(out, "Unknown opcode {}", byte)?;
+            return Ok(offset + 1);
+        }
+        Err(DecodeError::Truncated { .. }) => {
+            writeln!(out, "Truncated instruction")?;
+            return Ok(chunk.count);
+        }
+    };
+
+    let name = opcode_name(instruction.opcode());
+    match instruction {
+        Instruction::Constant(constant) | Instruction::ConstantAdd(constant) => {
+            match chunk.constants().get(constant as usize) {
+                Some(value) => writeln!(out, "{:16} {:4} '{}'", name, constant, value)?,
+                None => writeln!(out, "{:16} {:4} <invalid constant>", name, constant)?,
+            }
+        }
+        _ => match jump_target(offset, instruction) {
+            Some(target) => writeln!(out, "{:16} {:4} -> {}", name, offset, target)?,
+            None => writeln!(out, "{}", name)?,
+        },
+    }
+    Ok(offset + instruction.size())
+}
+
+/// Write an annotated listing of a chunk: each jump target notes the jumps
+/// that land on it and, given the source, each source line is printed
+/// above the first instruction compiled from it.
+pub fn write_listing<W: Write>(
+    out: &mut W,
+    chunk: &Chunk,
+    name: &str,
+    source: Option<&str>,
+) -> fmt::Result {
+    let mut sources = BTreeMap::<usize, Vec<usize>>::new();
+    for (offset, _, instruction) in chunk.decode().flatten() {
+        if let Some(target) = jump_target(offset, instruction) {
+            sources.entry(target).or_default().push(offset);
+        }
+    }
+    let source_lines: Vec<&str> = source.map(|s| s.lines().collect()).unwrap_or_default();
+
+    writeln!(out, "== {} ==", name)?;
+
+    let mut last_line = 0;
+    let mut offset = 0;
+    while offset < chunk.count {
+        let line = chunk.line(offset);
+        if line > last_line {
+            if let Some(text) = source_lines.get(line as usize - 1) {
+                writeln!(out, "{:>9} | {}", line, text.trim_end())?;
+            }
+            last_line = line;
+        }
+
+        let mut text = String::new();
+        let next = write_instruction(&mut text, chunk, offset)?;
+        let text = text.trim_end();
+        match sources.get(&offset) {
+            Some(from) => {
+                let from: Vec<_> = from.iter().map(|o| format!("{:04}", o)).collect();
+                writeln!(out, "{:40} ; from {}", text, from.join(", "))?
+            }
+            None => writeln!(out, "{}", text)?,
+        }
+        offset = next;
+    }
+    Ok(())
+}
+
+fn write_json_string<W: Write>(out: &mut W, string: &str) -> fmt::Result {
+    out.write_char('"')?;
+    for c in string.chars() {
+        match c {
+            '"' => out.write_str("\\\"")?,
+            '\\' => out.write_str("\\\\")?,
+            '\n' => out.write_str("\\n")?,
+            '\r' => out.write_str("\\r")?,
+            '\t' => out.write_str("\\t")?,
+            c if (c as u32) < 0x20 => write!(out, "\\u{:04x}", c as u32)?,
+            c => out.write_char(c)?,
+        }
+    }
+    out.write_char('"')
+}
+
+/// Write a chunk as a single JSON object with its constants and decoded
+/// instructions. A malformed instruction ends the list with an error entry.
+pub fn write_chunk_json<W: Write>(out: &mut W, chunk: &Chunk, name: &str) -> fmt::Result {
+    write!(out, "{{\"name\":")?;
+    write_json_string(out, name)?;
+
+    write!(out, ",\"constants\":[")?;
+    for (i, constant) in chunk.constants().iter().enumerate() {
+        if i > 0 {
+            out.write_char(',')?;
+        }
+        write_json_string(out, &constant.to_string())?;
+    }
+
+    write!(out, "],\"code\":[")?;
+    for (i, decoded) in chunk.decode().enumerate() {
+        if i > 0 {
+            out.write_char(',')?;
+        }
+        match decoded {
+            Ok((offset, line, instruction)) => {
+                write!(
+                    out,
+                    "{{\"offset\":{},\"line\":{},\"op\":\"{}\"",
+                    offset,
+                    line,
+                    opcode_name(instruction.opcode())
+                )?;
+                if let Instruction::Constant(constant) | Instruction::ConstantAdd(constant) =
+                    instruction
+                {
+                    write!(out, ",\"constant\":{}", constant)?;
+                    match chunk.constants().get(constant as usize) {
+                        Some(value) => {
+                            write!(out, ",\"value\":")?;
+                            write_json_string(out, &value.to_string())?;
+                        }
+                        None => write!(out, ",\"error\":\"Invalid constant.\"")?,
+                    }
+                }
+                if let Some(target) = jump_target(offset, instruction) {
+                    write!(out, ",\"target\":{}", target)?;
+                }
+                out.write_char('}')?;
+            }
+            Err(error) => {
+                write!(out, "{{\"error\":")?;
+                write_json_string(out, &error.to_string())?;
+                out.write_char('}')?;
+            }
+        }
+    }
+    writeln!(out, "]}}")
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::compiler::compile;
+
+    fn compiled(source: &str) -> Chunk {
+        let mut chunk = Chunk::new();
+        assert!(compile(source, &mut chunk));
+        chunk
+    }
+
+    #[test]
+    fn test_text_disassembly() {
+        let chunk = compiled("1 < 2 ?\n\"yes\" : nil");
+        let mut out = String::new();
+        write_chunk(&mut out, &chunk, "test").unwrap();
+
+        assert_eq!(
+            out,
+            "== test ==
+0000    1 OP_TRUE
+0001    | OP_JUMP_IF_FALSE    1 -> 10
+0004    | OP_POP
+0005    2 OP_CONSTANT         0 '\"yes\"'
+0007    | OP_JUMP             7 -> 12
+0010    | OP_POP
+0011    | OP_NIL
+0012    | OP_RETURN
+"
+        );
+    }
+
+    #[test]
+    fn test_listing() {
+        let source = "1 < 2 ?\n\"yes\" : nil";
+        let chunk = compiled(source);
+        let mut out = String::new();
+        write_listing(&mut out, &chunk, "test", Some(source)).unwrap();
+
+        assert_eq!(
+            out,
+            "== test ==
+        1 | 1 < 2 ?
+0000    1 OP_TRUE
+0001    | OP_JUMP_IF_FALSE    1 -> 10
+0004    | OP_POP
+        2 | \"yes\" : nil
+0005    2 OP_CONSTANT         0 '\"yes\"'
+0007    | OP_JUMP             7 -> 12
+0010    | OP_POP                         ; from 0001
+0011    | OP_NIL
+0012    | OP_RETURN                      ; from 0007
+"
+        );
+    }
+
+    #[test]
+    fn test_json_disassembly() {
+        let chunk = compiled("nil ?? \"a\"");
+        let mut out = Vec::new();
+        disassemble_to(&mut out, &chunk, "test", Format::Json).unwrap();
+
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            concat!(
+                r#"{"name":"test","constants":["\"a\""],"code":["#,
+                r#"{"offset":0,"line":1,"op":"OP_NIL"},"#,
+                r#"{"offset":1,"line":1,"op":"OP_JUMP_IF_NOT_NIL","target":7},"#,
+                r#"{"offset":4,"line":1,"op":"OP_POP"},"#,
+                r#"{"offset":5,"line":1,"op":"OP_CONSTANT","constant":0,"value":"\"a\""},"#,
+                r#"{"offset":7,"line":1,"op":"OP_RETURN"}]}"#,
+                "\n"
+            )
+        );
+    }
+
+    #[test]
+    fn test_invalid_constant() {
+        let mut chunk = Chunk::new();
+        chunk.write(OpCode::Constant as u8, 1);
+        chunk.write(5, 1);
+        chunk.write(OpCode::Return as u8, 1);
+
+        let mut out = String::new();
+        write_chunk(&mut out, &chunk, "test").unwrap();
+        assert_eq!(
+            out,
+            "== test ==
+0000    1 OP_CONSTANT         5 <invalid constant>
+0002    | OP_RETURN
+"
+        );
+
+        let mut out = Vec::new();
+        disassemble_to(&mut out, &chunk, "test", Format::Json).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            concat!(
+                r#"{"name":"test","constants":[],"code":["#,
+                r#"{"offset":0,"line":1,"op":"OP_CONSTANT","constant":5,"error":"Invalid constant."},"#,
+                r#"{"offset":2,"line":1,"op":"OP_RETURN"}]}"#,
+                "\n"
+            )
+        );
+    }
 }
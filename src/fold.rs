@@ -0,0 +1,234 @@
+//! Compile-time evaluation of operators whose operands are constants. Each
+//! function mirrors what the VM does for the same opcode, and gives up by
+//! returning None wherever the VM would raise an error, so that the error is
+//! still reported when the code runs.
+
+use crate::chunk::OpCode;
+use crate::object::Obj;
+use crate::value::Value;
+use crate::vm::is_falsey;
+
+pub(crate) fn is_unary(op: OpCode) -> bool {
+    matches!(op, OpCode::Not | OpCode::Negate | OpCode::BitNot)
+}
+
+pub(crate) fn unary(op: OpCode, a: Value) -> Option<Value> {
+    match op {
+        OpCode::Not => Some(is_falsey(a).into()),
+        OpCode::Negate if a.is_int() => a.as_int().checked_neg().map(Value::from),
+        OpCode::Negate if a.is_number() => Some((-a.as_number()).into()),
+        OpCode::BitNot if a.is_int() => Some((!a.as_int()).into()),
+        _ => None,
+    }
+}
+
+fn compare(a: Value, b: Value, op: fn(&Value, &Value) -> bool) -> Option<Value> {
+    if a.is_numeric() && b.is_numeric() {
+        Some(op(&a, &b).into())
+    } else {
+        None
+    }
+}
+
+fn arithmetic(
+    a: Value,
+    b: Value,
+    int: fn(i64, i64) -> Option<i64>,
+    float: fn(f64, f64) -> f64,
+) -> Option<Value> {
+    if a.is_int() && b.is_int() {
+        int(a.as_int(), b.as_int()).map(Value::from)
+    } else if a.is_numeric() && b.is_numeric() {
+        Some(float(a.as_numeric(), b.as_numeric()).into())
+    } else {
+        None
+    }
+}
+
+fn bitwise(a: Value, b: Value, op: fn(i64, i64) -> Option<i64>) -> Option<Value> {
+    if a.is_int() && b.is_int() {
+        op(a.as_int(), b.as_int()).map(Value::from)
+    } else {
+        None
+    }
+}
+
+pub(crate) fn binary(op: OpCode, a: Value, b: Value) -> Option<Value> {
+    match op {
+        OpCode::Equal => Some((a == b).into()),
+        OpCode::NotEqual => Some((a != b).into()),
+        OpCode::Greater => compare(a, b, Value::gt),
+        OpCode::Less => compare(a, b, Value::lt),
+        OpCode::GreaterEqual => compare(a, b, Value::ge),
+        OpCode::LessEqual => compare(a, b, Value::le),
+        OpCode::Add if a.is_string() && b.is_string() => {
+            Some(Obj::concat(a.as_string(), b.as_string()).into())
+        }
+        OpCode::Add => arithmetic(a, b, i64::checked_add, |a, b| a + b),
+        OpCode::Subtract => arithmetic(a, b, i64::checked_sub, |a, b| a - b),
+        OpCode::Multiply => arithmetic(a, b, i64::checked_mul, |a, b| a * b),
+        OpCode::Divide if a.is_numeric() && b.is_numeric() => {
+            Some((a.as_numeric() / b.as_numeric()).into())
+        }
+        // checked_div and checked_rem also give up on a zero divisor, which
+        // is an error
+        OpCode::IntDivide if a.is_int() && b.is_int() => {
+            a.as_int().checked_div(b.as_int()).map(Value::from)
+        }
+        OpCode::Modulo => arithmetic(a, b, i64::checked_rem, |a, b| a % b),
+        OpCode::Power if a.is_int() && b.is_int() && b.as_int() >= 0 => u32::try_from(b.as_int())
+            .ok()
+            .and_then(|b| a.as_int().checked_pow(b))
+            .map(Value::from),
+        OpCode::Power if a.is_numeric() && b.is_numeric() => {
+            Some(a.as_numeric().powf(b.as_numeric()).into())
+        }
+        OpCode::BitAnd => bitwise(a, b, |a, b| Some(a & b)),
+        OpCode::BitOr => bitwise(a, b, |a, b| Some(a | b)),
+        OpCode::BitXor => bitwise(a, b, |a, b| Some(a ^ b)),
+        OpCode::ShiftLeft => bitwise(a, b, |a, b| a.checked_shl(u32::try_from(b).ok()?)),
+        OpCode::ShiftRight => bitwise(a, b, |a, b| a.checked_shr(u32::try_from(b).ok()?)),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::chunk::{Chunk, ChunkBuilder, Instruction};
+    use crate::compiler::compile;
+    use crate::testing;
+
+    /// What the VM prints for `operands` followed by `op`, or None if it
+    /// raises an error
+    fn run(op: OpCode, operands: &[Value]) -> Option<String> {
+        let mut builder = ChunkBuilder::new();
+        for operand in operands {
+            let constant = builder.add_constant(*operand).unwrap();
+            builder.emit(Instruction::Constant(constant), 1);
+        }
+        builder.emit(Instruction::decode(&[op.into()], 0).unwrap(), 1);
+        builder.emit(Instruction::Return, 1);
+        testing::run(&builder.finish().unwrap())
+    }
+
+    fn folded(value: Option<Value>) -> Option<String> {
+        value.map(|value| format!("{}\n", value))
+    }
+
+    #[test]
+    fn test_folding_matches_the_vm() {
+        let values = [
+            Value::NIL,
+            true.into(),
+            false.into(),
+            0.into(),
+            3.into(),
+            (-7).into(),
+            64.into(),
+            3037000500.into(),
+            0.0.into(),
+            (-2.5).into(),
+            f64::NAN.into(),
+            Obj::copy_str("ab").into(),
+            Obj::copy_str("").into(),
+        ];
+
+        for op in [OpCode::Not, OpCode::Negate, OpCode::BitNot] {
+            for a in values {
+                assert_eq!(folded(unary(op, a)), run(op, &[a]), "{:?} {}", op, a);
+            }
+        }
+
+        for op in [
+            OpCode::Equal,
+            OpCode::NotEqual,
+            OpCode::Greater,
+            OpCode::Less,
+            OpCode::GreaterEqual,
+            OpCode::LessEqual,
+            OpCode::Add,
+            OpCode::Subtract,
+            OpCode::Multiply,
+            OpCode::Divide,
+            OpCode::IntDivide,
+            OpCode::Modulo,
+            OpCode::Power,
+            OpCode::BitAnd,
+            OpCode::BitOr,
+            OpCode::BitXor,
+            OpCode::ShiftLeft,
+            OpCode::ShiftRight,
+        ] {
+            for a in values {
+                for b in values {
+                    assert_eq!(
+                        folded(binary(op, a, b)),
+                        run(op, &[a, b]),
+                        "{} {:?} {}",
+                        a,
+                        op,
+                        b
+                    );
+                }
+            }
+        }
+    }
+
+    fn compiled(source: &str) -> (Vec<Instruction>, Vec<String>) {
+        let mut chunk = Chunk::new();
+        assert!(compile(source, &mut chunk));
+        let code = chunk.instructions().map(Result::unwrap).collect();
+        let constants = chunk.constants().iter().map(Value::to_string).collect();
+        (code, constants)
+    }
+
+    #[test]
+    fn test_compiler_folds_literals() {
+        use Instruction::*;
+
+        assert_eq!(
+            compiled("-1"),
+            (vec![Constant(0), Return], vec!["-1".into()])
+        );
+        assert_eq!(
+            compiled("2 * 3.25 + 1"),
+            (vec![Constant(0), Return], vec!["7.5".into()])
+        );
+        assert_eq!(compiled("!(1 < 2)"), (vec![False, Return], vec![]));
+        assert_eq!(
+            compiled("\"a\" + \"b\""),
+            (vec![Constant(0), Return], vec!["\"ab\"".into()])
+        );
+
+        // Errors are left for the VM to report at the right line
+        assert_eq!(
+            compiled("-\"x\""),
+            (vec![Constant(0), Negate, Return], vec!["\"x\"".into()])
+        );
+        assert_eq!(
+            compiled("1 << 64"),
+            (
+                vec![Constant(0), Constant(1), ShiftLeft, Return],
+                vec!["1".into(), "64".into()]
+            )
+        );
+
+        // Nothing is folded across a jump target
+        assert_eq!(
+            compiled("(nil ?? 1) + 2"),
+            (
+                vec![
+                    Nil,
+                    JumpIfNotNil(3),
+                    Pop,
+                    Constant(0),
+                    Constant(1),
+                    Add,
+                    Return
+                ],
+                vec!["1".into(), "2".into()]
+            )
+        );
+    }
+}
@@ -1,8 +1,21 @@
+pub mod ast;
 pub mod chunk;
 pub mod compiler;
+#[cfg(test)]
+mod conformance;
 pub mod debug;
+mod fold;
 pub mod memory;
 pub mod object;
+pub mod optimizer;
+pub mod peephole;
+pub mod register;
+pub mod repl;
 pub mod scanner;
+pub mod serialize;
+#[cfg(test)]
+mod testing;
+pub mod trace;
 pub mod value;
+pub mod verify;
 pub mod vm;
@@ -1,47 +1,313 @@
-use std::{env, fs, io, process};
+use std::{env, fs, process};
 
+use rlox::chunk::Chunk;
+use rlox::compiler::compile;
+use rlox::debug::write_listing;
+use rlox::optimizer::OptLevel;
+use rlox::register::RegisterVM;
+use rlox::repl;
+use rlox::serialize::{deserialize, serialize, MAGIC};
+use rlox::trace::TraceConfig;
 use rlox::vm::{InterpretResult, VM};
 
-fn read_file(filename: &str) -> String {
-    fs::read_to_string(filename).unwrap()
+const USAGE: &str = "Usage: rlox [options] [path [args...]]
+       rlox [options] run <path> [args...]
+       rlox [options] -e <code>
+       rlox repl
+       rlox check <path>...
+       rlox compile <path> -o <output>
+       rlox disasm [--source] <path>
+
+Options:
+  --trace[=<what>]  Trace execution: all, off, or a comma-separated list
+                    of instructions, stack and calls
+  -O0, -O1          Optimization level: -O1 (the default) removes dead
+                    code and threads jumps
+  --vm=<vm>         Run scripts on the stack (the default) or the
+                    experimental register VM
+  -h, --help        Print this message
+  -V, --version     Print the version";
+
+enum Command {
+    Repl,
+    Run {
+        path: String,
+        // Everything after the path, for the script's `args` global once
+        // the language has globals
+        #[allow(dead_code)]
+        args: Vec<String>,
+    },
+    Eval(String),
+    Check(Vec<String>),
+    Compile {
+        input: String,
+        output: String,
+    },
+    Disasm {
+        path: String,
+        source: bool,
+    },
+    Help,
+    Version,
+}
+
+struct Options {
+    command: Command,
+    trace: Option<TraceConfig>,
+    opt_level: OptLevel,
+    /// Run on the register VM instead of the stack VM
+    register: bool,
 }
 
-fn repl(vm: &mut VM) {
-    let mut line = String::new();
-    loop {
-        line.clear();
-        print!("> ");
-        if let Err(_) = io::stdin().read_line(&mut line) {
-            println!();
+fn is_subcommand(word: &str) -> bool {
+    matches!(word, "run" | "repl" | "check" | "compile" | "disasm")
+}
+
+fn parse_args(args: &[String]) -> Result<Options, String> {
+    let mut trace = None;
+    let mut opt_level = OptLevel::default();
+    let mut register = false;
+    let mut source = false;
+    let mut output = None;
+    let mut eval = None;
+    let mut positional = Vec::new();
+    let mut script_args = Vec::new();
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-h" | "--help" => {
+                return Ok(Options {
+                    command: Command::Help,
+                    trace,
+                    opt_level,
+                    register,
+                })
+            }
+            "-V" | "--version" => {
+                return Ok(Options {
+                    command: Command::Version,
+                    trace,
+                    opt_level,
+                    register,
+                })
+            }
+            "--trace" => trace = Some(TraceConfig::ALL),
+            "--source" => source = true,
+            "-o" => output = Some(args.next().ok_or("Expect a path after -o.")?.clone()),
+            "-e" => eval = Some(args.next().ok_or("Expect code after -e.")?.clone()),
+            arg if arg.starts_with("-O") => opt_level = arg[2..].parse()?,
+            "--vm=stack" => register = false,
+            "--vm=register" => register = true,
+            arg if arg.starts_with("--vm=") => return Err(format!("Unknown VM '{}'.", &arg[5..])),
+            arg => match arg.strip_prefix("--trace=") {
+                Some(spec) => trace = Some(spec.parse()?),
+                None if arg.starts_with('-') => return Err(format!("Unknown option '{}'.", arg)),
+                None => positional.push(arg.to_owned()),
+            },
+        }
+
+        // Everything after a script's path is passed to the script as-is
+        let script = match positional.as_slice() {
+            [path] => !is_subcommand(path),
+            [command, _] => command == "run",
+            _ => false,
+        };
+        if script {
+            script_args = args.cloned().collect();
             break;
         }
+    }
+
+    let positional: Vec<_> = positional.iter().map(String::as_str).collect();
+    let command = match (positional.as_slice(), eval, output) {
+        ([], Some(code), None) => Command::Eval(code),
+        (_, Some(_), _) => return Err("-e can't be combined with other commands.".to_owned()),
+        (["compile", input], None, Some(output)) => Command::Compile {
+            input: (*input).to_owned(),
+            output,
+        },
+        (_, None, Some(_)) => return Err("-o is only used with compile.".to_owned()),
+        ([], None, None) | (["repl"], None, None) => Command::Repl,
+        (["run", path], None, None) => Command::Run {
+            path: (*path).to_owned(),
+            args: script_args,
+        },
+        ([path], None, None) if !is_subcommand(path) => Command::Run {
+            path: (*path).to_owned(),
+            args: script_args,
+        },
+        (["check", paths @ ..], None, None) if !paths.is_empty() => {
+            Command::Check(paths.iter().map(|p| (*p).to_owned()).collect())
+        }
+        (["disasm", path], None, None) => Command::Disasm {
+            path: (*path).to_owned(),
+            source,
+        },
+        _ => return Err("Invalid arguments.".to_owned()),
+    };
+    if source && !matches!(command, Command::Disasm { .. }) {
+        return Err("--source is only used with disasm.".to_owned());
+    }
+    if register && !matches!(command, Command::Run { .. } | Command::Eval(_)) {
+        return Err("--vm=register is only used with run and -e.".to_owned());
+    }
+    if register && trace.is_some() {
+        return Err("The register VM can't be traced.".to_owned());
+    }
+
+    Ok(Options {
+        command,
+        trace,
+        opt_level,
+        register,
+    })
+}
 
-        vm.interpret(&line);
+fn read_file(filename: &str) -> Vec<u8> {
+    fs::read(filename).unwrap_or_else(|error| {
+        eprintln!("Could not open file \"{}\": {}", filename, error);
+        process::exit(74);
+    })
+}
+
+/// The scanner only handles ASCII, so reject anything else up front
+fn ascii(source: String) -> String {
+    if !source.is_ascii() {
+        eprintln!("Source must be ASCII.");
+        process::exit(65);
     }
+    source
 }
 
-fn run_file(vm: &mut VM, filename: &str) {
-    let source = read_file(filename);
-    match vm.interpret(&source) {
+fn read_source(filename: &str) -> String {
+    let source = String::from_utf8(read_file(filename)).unwrap_or_else(|_| {
+        eprintln!("Could not read file \"{}\": not valid UTF-8.", filename);
+        process::exit(74);
+    });
+    ascii(source)
+}
+
+fn exit_for(result: InterpretResult) {
+    match result {
         InterpretResult::Ok => {}
         InterpretResult::CompileError => process::exit(65),
         InterpretResult::RuntimeError => process::exit(70),
     }
 }
 
+fn run_file(vm: &mut VM, filename: &str) {
+    let contents = read_file(filename);
+    let result = if contents.starts_with(MAGIC) {
+        match deserialize(&contents) {
+            Ok(chunk) => vm.interpret_chunk(&chunk),
+            Err(error) => {
+                eprintln!("{}: {}", filename, error);
+                process::exit(65);
+            }
+        }
+    } else {
+        vm.interpret(&read_source(filename))
+    };
+
+    exit_for(result);
+}
+
+fn run_file_on_registers(filename: &str) {
+    let contents = read_file(filename);
+    if contents.starts_with(MAGIC) {
+        eprintln!("{}: the register VM can't run compiled chunks.", filename);
+        process::exit(65);
+    }
+    exit_for(RegisterVM::new().interpret(&read_source(filename)));
+}
+
+fn check_files(paths: &[String]) {
+    // Keep going after a failure so every file's errors are reported
+    let mut failed = false;
+    for path in paths {
+        let mut chunk = Chunk::new();
+        if !compile(&read_source(path), &mut chunk) {
+            eprintln!("{}: failed to compile", path);
+            failed = true;
+        }
+    }
+
+    if failed {
+        process::exit(65);
+    }
+}
+
+fn compile_source(source: &str, level: OptLevel) -> Chunk {
+    let mut chunk = Chunk::new();
+    if !compile(source, &mut chunk) {
+        process::exit(65);
+    }
+    level.apply(chunk).unwrap_or_else(|error| {
+        eprintln!("Could not optimize: {}", error);
+        process::exit(65);
+    })
+}
+
+fn compile_file(input: &str, output: &str, level: OptLevel) {
+    let chunk = compile_source(&read_source(input), level);
+    if let Err(error) = fs::write(output, serialize(&chunk)) {
+        eprintln!("Could not write {}: {}", output, error);
+        process::exit(74);
+    }
+}
+
+fn disassemble_file(path: &str, with_source: bool, level: OptLevel) {
+    let source = read_source(path);
+    let chunk = compile_source(&source, level);
+
+    let mut listing = String::new();
+    write_listing(
+        &mut listing,
+        &chunk,
+        "script",
+        with_source.then_some(&*source),
+    )
+    .unwrap();
+    print!("{}", listing);
+}
+
 fn main() {
+    let args: Vec<_> = env::args().skip(1).collect();
+    let options = parse_args(&args).unwrap_or_else(|error| {
+        eprintln!("{}", error);
+        eprintln!("{}", USAGE);
+        process::exit(64);
+    });
+
+    if options.register {
+        match options.command {
+            Command::Run { path, .. } => run_file_on_registers(&path),
+            Command::Eval(code) => exit_for(RegisterVM::new().interpret(&ascii(code))),
+            _ => unreachable!("parse_args only allows run and -e"),
+        }
+        process::exit(0);
+    }
+
     let mut vm = VM::new();
+    vm.set_opt_level(options.opt_level);
+    if let Some(config) = options.trace {
+        if cfg!(not(feature = "trace")) && !config.is_off() {
+            eprintln!("Tracing is not compiled into this build.");
+        }
+        vm.set_trace(config);
+    }
 
-    let args: Vec<_> = env::args().collect();
-    if args.len() == 1 {
-        repl(&mut vm);
-    } else if args.len() == 2 {
-        run_file(&mut vm, &args[1]);
-    } else {
-        eprintln!("Usage: rlox [path]");
-        process::exit(64);
+    match options.command {
+        Command::Repl => repl::run(&mut vm),
+        Command::Run { path, .. } => run_file(&mut vm, &path),
+        Command::Eval(code) => exit_for(vm.interpret(&ascii(code))),
+        Command::Check(paths) => check_files(&paths),
+        Command::Compile { input, output } => compile_file(&input, &output, options.opt_level),
+        Command::Disasm { path, source } => disassemble_file(&path, source, options.opt_level),
+        Command::Help => println!("{}", USAGE),
+        Command::Version => println!("rlox {}", env!("CARGO_PKG_VERSION")),
     }
 
-    drop(vm);
     process::exit(0);
 }
@@ -14,6 +14,11 @@ pub fn grow_capacity(capacity: usize) -> usize {
 
 #[inline]
 pub fn allocate<T>(count: usize) -> *mut T {
+    if count * mem::size_of::<T>() == 0 {
+        // Nothing to allocate, but callers still need a non-null pointer to
+        // build empty slices from
+        return ptr::NonNull::dangling().as_ptr();
+    }
     reallocate(ptr::null_mut(), 0, count * mem::size_of::<T>()) as *mut T
 }
 
@@ -23,17 +28,20 @@ pub fn free_array<T>(pointer: *mut T, old_size: usize) {
 
 pub fn grow_array<T>(pointer: *mut T, old_size: usize, new_size: usize) -> *mut T {
     let type_size = mem::size_of::<T>();
-    return reallocate(
+    reallocate(
         pointer as *mut u8,
         old_size * type_size,
         new_size * type_size,
-    ) as *mut T;
+    ) as *mut T
 }
 
+#[allow(clippy::not_unsafe_ptr_arg_deref)]
 pub fn reallocate(pointer: *mut u8, old_size: usize, new_size: usize) -> *mut u8 {
     if new_size == 0 {
-        unsafe {
-            dealloc(pointer, Layout::from_size_align(old_size, 8).unwrap());
+        if old_size != 0 {
+            unsafe {
+                dealloc(pointer, Layout::from_size_align(old_size, 8).unwrap());
+            }
         }
         return ptr::null_mut();
     }
@@ -49,7 +57,7 @@ pub fn reallocate(pointer: *mut u8, old_size: usize, new_size: usize) -> *mut u8
             new_size,
         )
     };
-    if new_ptr == ptr::null_mut() {
+    if new_ptr.is_null() {
         pointer
     } else {
         new_ptr
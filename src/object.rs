@@ -9,13 +9,29 @@ pub enum Obj {
 }
 
 impl Obj {
-    pub fn copy_string(string: &str) -> *mut Self {
+    /// Copy a string literal's lexeme, without its quotes
+    pub fn copy_string(lexeme: &str) -> *mut Self {
+        Self::copy_str(&lexeme[1..lexeme.len() - 1])
+    }
+
+    pub fn copy_str(string: &str) -> *mut Self {
         ObjString::from_str(string).into()
     }
 
     pub fn take_string(chars: *mut u8, length: usize) -> *mut Self {
         ObjString::new(chars, length).into()
     }
+
+    /// A new string holding `a` followed by `b`
+    pub fn concat(a: &ObjString, b: &ObjString) -> *mut Self {
+        let length = a.length + b.length;
+        let chars = memory::allocate(length);
+        unsafe {
+            copy_nonoverlapping(a.chars, chars, a.length);
+            copy_nonoverlapping(b.chars, chars.add(a.length), b.length);
+        }
+        Self::take_string(chars, length)
+    }
 }
 
 impl Display for Obj {
@@ -33,7 +49,7 @@ pub struct ObjString {
 }
 
 impl ObjString {
-    fn as_ruststr(&self) -> &str {
+    pub(crate) fn as_ruststr(&self) -> &str {
         unsafe {
             let slice = slice::from_raw_parts_mut(self.chars, self.length);
             str::from_utf8_unchecked(slice)
@@ -41,10 +57,10 @@ impl ObjString {
     }
 
     fn from_str(slice: &str) -> Self {
-        let length = slice.len() - 2;
+        let length = slice.len();
         let heap_chars = memory::allocate(length);
         unsafe {
-            copy_nonoverlapping(slice.as_ptr().add(1), heap_chars, length);
+            copy_nonoverlapping(slice.as_ptr(), heap_chars, length);
         }
         Self {
             length,
@@ -63,8 +79,8 @@ impl PartialEq for ObjString {
     }
 }
 
-impl Into<*mut Obj> for ObjString {
-    fn into(self) -> *mut Obj {
-        Box::into_raw(Box::new(Obj::String(self)))
+impl From<ObjString> for *mut Obj {
+    fn from(string: ObjString) -> Self {
+        Box::into_raw(Box::new(Obj::String(string)))
     }
 }
@@ -0,0 +1,366 @@
+use std::collections::BTreeMap;
+use std::str::FromStr;
+
+use crate::chunk::{BuildError, Chunk, ChunkBuilder, Instruction, Label, OpCode};
+use crate::debug::jump_target;
+use crate::peephole;
+use crate::value::Value;
+use crate::verify::verify;
+use crate::vm::is_falsey;
+
+/// How much work to put into compiled code before it runs
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub enum OptLevel {
+    /// Run what the compiler produced
+    O0,
+    /// Remove dead code and thread jumps with [`optimize`]
+    #[default]
+    O1,
+}
+
+impl FromStr for OptLevel {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "0" => Ok(Self::O0),
+            "1" => Ok(Self::O1),
+            _ => Err(format!("Unknown optimization level '{}'.", s)),
+        }
+    }
+}
+
+impl OptLevel {
+    /// Optimize a chunk the compiler produced to this level
+    pub fn apply(self, chunk: Chunk) -> Result<Chunk, BuildError> {
+        match self {
+            Self::O0 => Ok(chunk),
+            Self::O1 => optimize(&chunk),
+        }
+    }
+}
+
+struct Op {
+    line: u32,
+    instruction: Instruction,
+    // For a jump, the index of the instruction it lands on
+    target: Option<usize>,
+}
+
+/// The instructions of a chunk, with deleted ones left as holes so that
+/// jump targets stay valid. A jump to a hole lands on the next instruction
+/// that is still there.
+struct Code<'a> {
+    constants: &'a [Value],
+    ops: Vec<Option<Op>>,
+}
+
+impl Code<'_> {
+    fn next_live(&self, index: usize) -> usize {
+        (index..self.ops.len())
+            .find(|&i| self.ops[i].is_some())
+            .unwrap_or(self.ops.len())
+    }
+
+    fn op(&self, index: usize) -> Option<&Op> {
+        self.ops.get(index).and_then(Option::as_ref)
+    }
+
+    fn opcode(&self, index: usize) -> Option<OpCode> {
+        self.op(index).map(|op| op.instruction.opcode())
+    }
+
+    fn target(&self, index: usize) -> Option<usize> {
+        self.op(index)?.target.map(|target| self.next_live(target))
+    }
+
+    /// Which instructions jumps land on. Deleting an instruction moves the
+    /// jumps to it onto the next one, so this goes stale after any change.
+    fn targets(&self) -> Vec<bool> {
+        let mut next_live = vec![self.ops.len(); self.ops.len() + 1];
+        for index in (0..self.ops.len()).rev() {
+            next_live[index] = match self.ops[index] {
+                Some(_) => index,
+                None => next_live[index + 1],
+            };
+        }
+
+        let mut targets = vec![false; self.ops.len() + 1];
+        for target in self.ops.iter().flatten().filter_map(|op| op.target) {
+            targets[next_live[target]] = true;
+        }
+        targets
+    }
+
+    /// The value an instruction pushes, if it's known
+    fn literal(&self, index: usize) -> Option<Value> {
+        match self.op(index)?.instruction {
+            Instruction::Nil => Some(Value::NIL),
+            Instruction::True => Some(true.into()),
+            Instruction::False => Some(false.into()),
+            Instruction::Constant(constant) => Some(self.constants[constant as usize]),
+            _ => None,
+        }
+    }
+
+    fn make_jump(&mut self, index: usize) {
+        if let Some(op) = &mut self.ops[index] {
+            op.instruction = Instruction::Jump(0);
+        }
+    }
+
+    /// Rewrite the jump at `index`, if it can be
+    fn simplify_jump(&mut self, index: usize) -> bool {
+        let (Some(op), Some(target)) = (self.opcode(index), self.target(index)) else {
+            return false;
+        };
+
+        // A jump to the next instruction does nothing, except for
+        // JumpIfFalsePop which still pops a truthy value
+        if target == self.next_live(index + 1) && op != OpCode::JumpIfFalsePop {
+            self.ops[index] = None;
+            return true;
+        }
+
+        // Thread through a jump that is sure to be taken when this one is.
+        // None of these jumps change the stack when they are taken.
+        let threads = matches!(
+            (op, self.opcode(target)),
+            (_, Some(OpCode::Jump))
+                | (
+                    OpCode::JumpIfFalse | OpCode::JumpIfFalsePop,
+                    Some(OpCode::JumpIfFalse | OpCode::JumpIfFalsePop),
+                )
+                | (OpCode::JumpIfNotNil, Some(OpCode::JumpIfNotNil))
+        );
+        if threads {
+            let through = self.ops[target].as_ref().unwrap().target;
+            self.ops[index].as_mut().unwrap().target = through;
+        }
+        threads
+    }
+
+    /// Resolve what follows a literal at `index` when it only depends on
+    /// the literal's value
+    fn simplify_literal(&mut self, index: usize, targets: &[bool]) -> bool {
+        let Some(value) = self.literal(index) else {
+            return false;
+        };
+        let next = self.next_live(index + 1);
+        let Some(op) = self.opcode(next) else {
+            return false;
+        };
+        // Another path into the next instruction could bring another value
+        if targets[next] {
+            return false;
+        }
+
+        match op {
+            OpCode::Pop => {
+                self.ops[index] = None;
+                self.ops[next] = None;
+            }
+            OpCode::JumpIfFalse | OpCode::JumpIfFalsePop if is_falsey(value) => {
+                self.make_jump(next)
+            }
+            OpCode::JumpIfFalse => self.ops[next] = None,
+            OpCode::JumpIfFalsePop => {
+                self.ops[index] = None;
+                self.ops[next] = None;
+            }
+            OpCode::JumpIfNotNil if value.is_nil() => self.ops[next] = None,
+            OpCode::JumpIfNotNil => self.make_jump(next),
+            _ => return false,
+        }
+        true
+    }
+
+    /// Delete the instructions no path from the start reaches
+    fn remove_unreachable(&mut self) -> bool {
+        let mut reachable = vec![false; self.ops.len()];
+        let mut pending = vec![self.next_live(0)];
+        while let Some(index) = pending.pop() {
+            if index >= self.ops.len() || reachable[index] {
+                continue;
+            }
+            reachable[index] = true;
+
+            let op = self.opcode(index).unwrap();
+            if let Some(target) = self.target(index) {
+                pending.push(target);
+            }
+            if !matches!(op, OpCode::Jump | OpCode::Return) {
+                pending.push(self.next_live(index + 1));
+            }
+        }
+
+        let mut changed = false;
+        for (op, reachable) in self.ops.iter_mut().zip(reachable) {
+            if op.is_some() && !reachable {
+                *op = None;
+                changed = true;
+            }
+        }
+        changed
+    }
+}
+
+/// Remove dead code from a chunk: branches on constant conditions,
+/// unreachable instructions, jumps that go nowhere and constants nothing
+/// loads. Jumps to jumps are threaded to their final destination. Last,
+/// the peephole pass fuses the pairs of instructions that are left.
+pub fn optimize(chunk: &Chunk) -> Result<Chunk, BuildError> {
+    let Ok(decoded) = chunk.decode().collect::<Result<Vec<_>, _>>() else {
+        return Err(BuildError::Invalid(verify(chunk).unwrap_err()));
+    };
+
+    let index_of: BTreeMap<usize, usize> = decoded
+        .iter()
+        .enumerate()
+        .map(|(index, &(offset, _, _))| (offset, index))
+        .collect();
+    let mut code = Code {
+        constants: chunk.constants(),
+        ops: decoded
+            .iter()
+            .map(|&(offset, line, instruction)| {
+                Some(Op {
+                    line,
+                    instruction,
+                    target: jump_target(offset, instruction)
+                        .map(|target| index_of.get(&target).copied().unwrap_or(decoded.len())),
+                })
+            })
+            .collect(),
+    };
+
+    loop {
+        let mut changed = code.remove_unreachable();
+        let mut targets = code.targets();
+        for index in 0..code.ops.len() {
+            if code.simplify_jump(index) || code.simplify_literal(index, &targets) {
+                targets = code.targets();
+                changed = true;
+            }
+        }
+        if !changed {
+            break;
+        }
+    }
+
+    // Renumber the constants that are still used, keeping their order
+    let mut builder = ChunkBuilder::new();
+    let mut constants = BTreeMap::new();
+    for op in code.ops.iter().flatten() {
+        if let Instruction::Constant(c) | Instruction::ConstantAdd(c) = op.instruction {
+            constants.insert(c, 0);
+        }
+    }
+    for (&old, new) in constants.iter_mut() {
+        *new = builder.add_constant(chunk.constants()[old as usize])?;
+    }
+
+    let mut labels = BTreeMap::<usize, Label>::new();
+    for index in 0..code.ops.len() {
+        if let Some(target) = code.target(index) {
+            labels.entry(target).or_insert_with(|| builder.new_label());
+        }
+    }
+
+    for (index, op) in code.ops.iter().enumerate() {
+        let Some(op) = op else { continue };
+        if let Some(&label) = labels.get(&index) {
+            builder.bind(label)?;
+        }
+        match op.instruction {
+            Instruction::Constant(c) => builder.emit(Instruction::Constant(constants[&c]), op.line),
+            Instruction::ConstantAdd(c) => {
+                builder.emit(Instruction::ConstantAdd(constants[&c]), op.line)
+            }
+            instruction if op.target.is_some() => builder.jump(
+                instruction.opcode(),
+                labels[&code.target(index).unwrap()],
+                op.line,
+            )?,
+            instruction => builder.emit(instruction, op.line),
+        }
+    }
+
+    peephole::optimize(&builder.finish()?)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::compiler::compile;
+    use crate::object::Obj;
+    use crate::testing::run;
+
+    fn optimized(source: &str) -> Chunk {
+        let mut chunk = Chunk::new();
+        assert!(compile(source, &mut chunk));
+        optimize(&chunk).unwrap()
+    }
+
+    fn instructions(chunk: &Chunk) -> Vec<Instruction> {
+        chunk.instructions().map(Result::unwrap).collect()
+    }
+
+    #[test]
+    fn test_removes_constant_branches() {
+        use Instruction::*;
+
+        let chunk = optimized("true ? \"yes\" : \"no\"");
+        assert_eq!(instructions(&chunk), [Constant(0), Return]);
+        assert_eq!(chunk.constants(), &[Obj::copy_str("yes").into()]);
+
+        let chunk = optimized("false ?\n\"yes\" :\n\"no\"");
+        assert_eq!(instructions(&chunk), [Constant(0), Return]);
+        assert_eq!(chunk.constants(), &[Obj::copy_str("no").into()]);
+        assert_eq!(chunk.lines(), &[3, 3, 3]);
+
+        let chunk = optimized("1 + (nil ?? 2)");
+        assert_eq!(instructions(&chunk), [Constant(0), ConstantAdd(1), Return]);
+
+        let chunk = optimized("(0 ?? 2) + 1");
+        assert_eq!(instructions(&chunk), [Constant(0), ConstantAdd(1), Return]);
+        assert_eq!(chunk.constants(), &[0.into(), 1.into()]);
+    }
+
+    #[test]
+    fn test_threads_jumps() {
+        // The optimizer doesn't fold, so these conditions stay. The inner
+        // conditional's jump to its end lands on the outer one's.
+        let source = "(nil ?? 1) < 2 ? ((nil ?? 3) < 4 ? 5 : 6) : 7";
+        let chunk = optimized(source);
+        for (offset, _, instruction) in chunk.decode().flatten() {
+            if let Some(target) = jump_target(offset, instruction) {
+                let landing = Instruction::decode(chunk.code(), target).unwrap();
+                assert_ne!(landing.opcode(), OpCode::Jump, "{} -> {}", offset, target);
+            }
+        }
+        assert_eq!(run(&chunk), Some("5\n".to_owned()));
+    }
+
+    #[test]
+    fn test_preserves_results() {
+        for source in [
+            "true ? 1 : 2",
+            "nil ? 1 : 2",
+            "0 ? 1 : 2",
+            "nil ?? false ?? 3",
+            "(nil ?? false) ?? 3",
+            "(nil ?? 1) < 2 ? (false ? 1 : nil) ?? 4 : 5",
+            "((nil ?? 1) > 2 ? true : nil) ? 3 : 4",
+            "(nil ?? 1) + \"x\"",
+            "(true ? 1 : nil) ?? -\"x\"",
+            "(false ? 1 : nil) ?? -\"x\"",
+        ] {
+            let mut chunk = Chunk::new();
+            assert!(compile(source, &mut chunk));
+            let optimized = optimize(&chunk).unwrap();
+            assert!(optimized.count <= chunk.count, "{}", source);
+            assert_eq!(run(&optimized), run(&chunk), "{}", source);
+        }
+    }
+}
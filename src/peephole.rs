@@ -0,0 +1,167 @@
+use std::collections::BTreeMap;
+
+use crate::chunk::{BuildError, Chunk, ChunkBuilder, Instruction, Label};
+use crate::debug::jump_target;
+use crate::verify::verify;
+
+/// The superinstruction a pair of adjacent instructions can be replaced by
+fn fuse(first: Instruction, second: Instruction) -> Option<Instruction> {
+    match (first, second) {
+        (Instruction::Constant(constant), Instruction::Add) => {
+            Some(Instruction::ConstantAdd(constant))
+        }
+        (Instruction::JumpIfFalse(jump), Instruction::Pop) => {
+            Some(Instruction::JumpIfFalsePop(jump))
+        }
+        _ => None,
+    }
+}
+
+/// Rewrite a chunk with common instruction pairs fused into
+/// superinstructions. A pair is only fused when nothing jumps to its
+/// second instruction, and jumps are relocated to account for the
+/// shorter code.
+pub fn optimize(chunk: &Chunk) -> Result<Chunk, BuildError> {
+    // The output is verified when it's built, so the input only needs to
+    // decode. If it doesn't, verify explains why.
+    let Ok(decoded) = chunk.decode().collect::<Result<Vec<_>, _>>() else {
+        return Err(BuildError::Invalid(verify(chunk).unwrap_err()));
+    };
+
+    let mut builder = ChunkBuilder::new();
+    for constant in chunk.constants() {
+        builder.add_constant(*constant)?;
+    }
+
+    let mut labels = BTreeMap::<usize, Label>::new();
+    for &(offset, _, instruction) in &decoded {
+        if let Some(target) = jump_target(offset, instruction) {
+            labels.entry(target).or_insert_with(|| builder.new_label());
+        }
+    }
+
+    let mut i = 0;
+    while i < decoded.len() {
+        let (offset, mut line, mut instruction) = decoded[i];
+        if let Some(&label) = labels.get(&offset) {
+            builder.bind(label)?;
+        }
+        let target = jump_target(offset, instruction);
+
+        if let Some(&(next_offset, next_line, next)) = decoded.get(i + 1) {
+            if let Some(fused) =
+                fuse(instruction, next).filter(|_| !labels.contains_key(&next_offset))
+            {
+                // A failing add is reported on the line of the operator
+                if let Instruction::ConstantAdd(_) = fused {
+                    line = next_line;
+                }
+                instruction = fused;
+                i += 1;
+            }
+        }
+
+        match target {
+            Some(target) => builder.jump(instruction.opcode(), labels[&target], line)?,
+            None => builder.emit(instruction, line),
+        }
+        i += 1;
+    }
+
+    builder.finish()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::chunk::OpCode;
+    use crate::compiler::compile;
+
+    fn opcodes(chunk: &Chunk) -> Vec<OpCode> {
+        chunk
+            .instructions()
+            .map(|instruction| instruction.unwrap().opcode())
+            .collect()
+    }
+
+    #[test]
+    fn test_fuses_pairs() {
+        let mut chunk = Chunk::new();
+        assert!(compile("(nil ?? 1) + 2 + 3", &mut chunk));
+        assert_eq!(
+            opcodes(&optimize(&chunk).unwrap()),
+            [
+                OpCode::Nil,
+                OpCode::JumpIfNotNil,
+                OpCode::Pop,
+                OpCode::Constant,
+                OpCode::ConstantAdd,
+                OpCode::ConstantAdd,
+                OpCode::Return
+            ]
+        );
+    }
+
+    #[test]
+    fn test_relocates_jumps() {
+        let mut builder = ChunkBuilder::new();
+        let one = builder.add_constant(1.into()).unwrap();
+        let else_label = builder.new_label();
+        let end_label = builder.new_label();
+        builder.emit(Instruction::Constant(one), 1);
+        builder.emit(Instruction::True, 1);
+        builder.jump(OpCode::JumpIfFalse, else_label, 1).unwrap();
+        builder.emit(Instruction::Pop, 1);
+        builder.emit(Instruction::Constant(one), 2);
+        builder.emit(Instruction::Add, 3);
+        builder.jump(OpCode::Jump, end_label, 3).unwrap();
+        builder.bind(else_label).unwrap();
+        builder.emit(Instruction::Pop, 4);
+        builder.bind(end_label).unwrap();
+        builder.emit(Instruction::Return, 4);
+        let chunk = optimize(&builder.finish().unwrap()).unwrap();
+
+        assert_eq!(
+            chunk.instructions().collect::<Result<Vec<_>, _>>(),
+            Ok(vec![
+                Instruction::Constant(0),
+                Instruction::True,
+                Instruction::JumpIfFalsePop(5),
+                Instruction::ConstantAdd(0),
+                Instruction::Jump(1),
+                Instruction::Pop,
+                Instruction::Return,
+            ])
+        );
+        assert_eq!(chunk.line(6), 3);
+    }
+
+    #[test]
+    fn test_keeps_pairs_split_by_a_jump_target() {
+        let mut builder = ChunkBuilder::new();
+        let one = builder.add_constant(1.into()).unwrap();
+        let label = builder.new_label();
+        builder.emit(Instruction::Constant(one), 1);
+        builder.emit(Instruction::Nil, 1);
+        builder.jump(OpCode::JumpIfNotNil, label, 1).unwrap();
+        builder.emit(Instruction::Pop, 1);
+        builder.emit(Instruction::Constant(one), 1);
+        builder.bind(label).unwrap();
+        builder.emit(Instruction::Add, 1);
+        builder.emit(Instruction::Return, 1);
+        let chunk = optimize(&builder.finish().unwrap()).unwrap();
+
+        assert_eq!(
+            opcodes(&chunk),
+            [
+                OpCode::Constant,
+                OpCode::Nil,
+                OpCode::JumpIfNotNil,
+                OpCode::Pop,
+                OpCode::Constant,
+                OpCode::Add,
+                OpCode::Return,
+            ]
+        );
+    }
+}
@@ -0,0 +1,231 @@
+use std::fmt::Display;
+
+use super::{Instruction, Program, Reg};
+use crate::ast::{fold_constants, BinaryOp, Expr, ExprKind, Literal, Script, UnaryOp};
+use crate::object::Obj;
+use crate::value::Value;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct GenerateError {
+    pub message: &'static str,
+    pub line: u32,
+}
+
+impl Display for GenerateError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "[line {}] Error: {}", self.line, self.message)
+    }
+}
+
+struct Generator {
+    program: Program,
+}
+
+impl Generator {
+    fn emit(&mut self, instruction: Instruction, line: u32) -> Result<usize, GenerateError> {
+        if self.program.code.len() > u16::MAX as usize {
+            return Err(GenerateError {
+                message: "Too much code to jump over.",
+                line,
+            });
+        }
+        self.program.code.push(instruction);
+        self.program.lines.push(line);
+        Ok(self.program.code.len() - 1)
+    }
+
+    /// Point the jump at `index` to the next instruction
+    fn patch(&mut self, index: usize) {
+        let here = self.program.code.len() as u16;
+        match &mut self.program.code[index] {
+            Instruction::Jump { target }
+            | Instruction::JumpIfFalse { target, .. }
+            | Instruction::JumpIfNotNil { target, .. } => *target = here,
+            _ => unreachable!(),
+        }
+    }
+
+    fn register(&mut self, reg: usize, line: u32) -> Result<Reg, GenerateError> {
+        let reg = Reg::try_from(reg).map_err(|_| GenerateError {
+            message: "Expression too deeply nested.",
+            line,
+        })?;
+        self.program.registers = self.program.registers.max(reg as usize + 1);
+        Ok(reg)
+    }
+
+    fn literal(&mut self, literal: &Literal, dst: Reg, line: u32) -> Result<(), GenerateError> {
+        let value: Value = match literal {
+            Literal::Nil => return self.emit(Instruction::Nil { dst }, line).map(drop),
+            Literal::Bool(true) => return self.emit(Instruction::True { dst }, line).map(drop),
+            Literal::Bool(false) => return self.emit(Instruction::False { dst }, line).map(drop),
+            Literal::Int(n) => (*n).into(),
+            Literal::Number(n) => (*n).into(),
+            Literal::String(s) => Obj::copy_str(s).into(),
+        };
+
+        let constant = u8::try_from(self.program.constants.len()).map_err(|_| GenerateError {
+            message: "Too many constants in one chunk.",
+            line,
+        })?;
+        self.program.constants.push(value);
+        self.emit(Instruction::Constant { dst, constant }, line)
+            .map(drop)
+    }
+
+    /// Generate code that leaves the value of `expr` in `dst`, using the
+    /// slots above it as temporaries. Lines are attributed as in the
+    /// bytecode compiler.
+    fn expression(&mut self, expr: &Expr, dst: Reg) -> Result<(), GenerateError> {
+        let line = expr.span.end_line;
+
+        match &expr.kind {
+            ExprKind::Literal(literal) => self.literal(literal, dst, line)?,
+            ExprKind::Grouping(inner) => self.expression(inner, dst)?,
+            ExprKind::Unary { op, operand } => {
+                self.expression(operand, dst)?;
+                let src = dst;
+                let instruction = match op {
+                    UnaryOp::Not => Instruction::Not { dst, src },
+                    UnaryOp::Negate => Instruction::Negate { dst, src },
+                    UnaryOp::BitNot => Instruction::BitNot { dst, src },
+                };
+                self.emit(instruction, line)?;
+            }
+            ExprKind::Binary { op, left, right } => {
+                let (a, b) = (dst, self.register(dst as usize + 1, line)?);
+                self.expression(left, a)?;
+                self.expression(right, b)?;
+                self.emit(binary(*op, dst, a, b), line)?;
+            }
+            ExprKind::Conditional {
+                condition,
+                then_branch,
+                else_branch,
+                question,
+            } => {
+                self.expression(condition, dst)?;
+                let else_jump = self.emit(
+                    Instruction::JumpIfFalse {
+                        src: dst,
+                        target: 0,
+                    },
+                    question.line,
+                )?;
+                self.expression(then_branch, dst)?;
+                let end_jump =
+                    self.emit(Instruction::Jump { target: 0 }, then_branch.span.end_line)?;
+                self.patch(else_jump);
+                self.expression(else_branch, dst)?;
+                self.patch(end_jump);
+            }
+            ExprKind::Coalesce {
+                left,
+                right,
+                operator,
+            } => {
+                self.expression(left, dst)?;
+                let end_jump = self.emit(
+                    Instruction::JumpIfNotNil {
+                        src: dst,
+                        target: 0,
+                    },
+                    operator.line,
+                )?;
+                self.expression(right, dst)?;
+                self.patch(end_jump);
+            }
+        }
+        Ok(())
+    }
+}
+
+fn binary(op: BinaryOp, dst: Reg, a: Reg, b: Reg) -> Instruction {
+    match op {
+        BinaryOp::Equal => Instruction::Equal { dst, a, b },
+        BinaryOp::NotEqual => Instruction::NotEqual { dst, a, b },
+        BinaryOp::Greater => Instruction::Greater { dst, a, b },
+        BinaryOp::GreaterEqual => Instruction::GreaterEqual { dst, a, b },
+        BinaryOp::Less => Instruction::Less { dst, a, b },
+        BinaryOp::LessEqual => Instruction::LessEqual { dst, a, b },
+        BinaryOp::Add => Instruction::Add { dst, a, b },
+        BinaryOp::Subtract => Instruction::Subtract { dst, a, b },
+        BinaryOp::Multiply => Instruction::Multiply { dst, a, b },
+        BinaryOp::Divide => Instruction::Divide { dst, a, b },
+        BinaryOp::IntDivide => Instruction::IntDivide { dst, a, b },
+        BinaryOp::Modulo => Instruction::Modulo { dst, a, b },
+        BinaryOp::Power => Instruction::Power { dst, a, b },
+        BinaryOp::BitAnd => Instruction::BitAnd { dst, a, b },
+        BinaryOp::BitOr => Instruction::BitOr { dst, a, b },
+        BinaryOp::BitXor => Instruction::BitXor { dst, a, b },
+        BinaryOp::ShiftLeft => Instruction::ShiftLeft { dst, a, b },
+        BinaryOp::ShiftRight => Instruction::ShiftRight { dst, a, b },
+    }
+}
+
+/// Generate a register program for a script. The result of the script is
+/// left in slot 0.
+pub fn generate(script: &Script) -> Result<Program, GenerateError> {
+    let mut generator = Generator {
+        program: Program::default(),
+    };
+    let result = generator.register(0, script.eof.line)?;
+    generator.expression(&fold_constants(&script.body), result)?;
+    generator.emit(Instruction::Return { src: result }, script.eof.line)?;
+    Ok(generator.program)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::ast::parse;
+    use Instruction::*;
+
+    fn generated(source: &str) -> Program {
+        generate(&parse(source).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn test_three_address_code() {
+        let program = generated("(nil ?? 1) < 2 ?\n-\"x\" : 3");
+        assert_eq!(
+            program.code(),
+            [
+                Nil { dst: 0 },
+                JumpIfNotNil { src: 0, target: 3 },
+                Constant {
+                    dst: 0,
+                    constant: 0
+                },
+                Constant {
+                    dst: 1,
+                    constant: 1
+                },
+                Less { dst: 0, a: 0, b: 1 },
+                JumpIfFalse { src: 0, target: 9 },
+                Constant {
+                    dst: 0,
+                    constant: 2
+                },
+                Negate { dst: 0, src: 0 },
+                Jump { target: 10 },
+                Constant {
+                    dst: 0,
+                    constant: 3
+                },
+                Return { src: 0 },
+            ]
+        );
+        assert_eq!(program.lines(), [1, 1, 1, 1, 1, 1, 2, 2, 2, 2, 2]);
+        assert_eq!(program.registers(), 2);
+    }
+
+    #[test]
+    fn test_registers_follow_nesting() {
+        assert_eq!(generated("1").registers(), 1);
+        // Folded away entirely
+        assert_eq!(generated("1 + (2 * (3 - 4))").registers(), 1);
+        let nested = "(nil ?? 1) + ((nil ?? 2) * ((nil ?? 3) - (nil ?? 4)))";
+        assert_eq!(generated(nested).registers(), 4);
+    }
+}
@@ -0,0 +1,78 @@
+//! An experimental register-based VM. Instead of pushing and popping
+//! operands, each instruction names the frame slots it reads and writes,
+//! so `a + b` is a single `Add { dst, a, b }`. Programs are generated from
+//! the AST, after the same constant folding the bytecode compiler does, so
+//! both VMs run the same program and can be compared directly.
+
+mod codegen;
+mod vm;
+
+pub use codegen::{generate, GenerateError};
+pub use vm::RegisterVM;
+
+use crate::value::Value;
+
+/// A slot in the frame
+pub type Reg = u8;
+
+/// Three-address instructions. Jump targets are indexes into the code.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Instruction {
+    Constant { dst: Reg, constant: u8 },
+    Nil { dst: Reg },
+    True { dst: Reg },
+    False { dst: Reg },
+    Equal { dst: Reg, a: Reg, b: Reg },
+    NotEqual { dst: Reg, a: Reg, b: Reg },
+    Greater { dst: Reg, a: Reg, b: Reg },
+    GreaterEqual { dst: Reg, a: Reg, b: Reg },
+    Less { dst: Reg, a: Reg, b: Reg },
+    LessEqual { dst: Reg, a: Reg, b: Reg },
+    Add { dst: Reg, a: Reg, b: Reg },
+    Subtract { dst: Reg, a: Reg, b: Reg },
+    Multiply { dst: Reg, a: Reg, b: Reg },
+    Divide { dst: Reg, a: Reg, b: Reg },
+    IntDivide { dst: Reg, a: Reg, b: Reg },
+    Modulo { dst: Reg, a: Reg, b: Reg },
+    Power { dst: Reg, a: Reg, b: Reg },
+    BitAnd { dst: Reg, a: Reg, b: Reg },
+    BitOr { dst: Reg, a: Reg, b: Reg },
+    BitXor { dst: Reg, a: Reg, b: Reg },
+    ShiftLeft { dst: Reg, a: Reg, b: Reg },
+    ShiftRight { dst: Reg, a: Reg, b: Reg },
+    Not { dst: Reg, src: Reg },
+    Negate { dst: Reg, src: Reg },
+    BitNot { dst: Reg, src: Reg },
+    Jump { target: u16 },
+    JumpIfFalse { src: Reg, target: u16 },
+    JumpIfNotNil { src: Reg, target: u16 },
+    Return { src: Reg },
+}
+
+/// The register equivalent of a chunk
+#[derive(Debug, Default)]
+pub struct Program {
+    code: Vec<Instruction>,
+    lines: Vec<u32>,
+    constants: Vec<Value>,
+    /// How many slots the frame needs
+    registers: usize,
+}
+
+impl Program {
+    pub fn code(&self) -> &[Instruction] {
+        &self.code
+    }
+
+    pub fn lines(&self) -> &[u32] {
+        &self.lines
+    }
+
+    pub fn constants(&self) -> &[Value] {
+        &self.constants
+    }
+
+    pub fn registers(&self) -> usize {
+        self.registers
+    }
+}
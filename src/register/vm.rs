@@ -0,0 +1,237 @@
+use std::io::{self, Write};
+
+use super::{generate, Instruction, Program};
+use crate::ast::parse;
+use crate::chunk::OpCode;
+use crate::fold;
+use crate::object::Obj;
+use crate::value::Value;
+use crate::vm::{is_falsey, InterpretResult};
+
+/// The message the stack VM reports when `op` fails on these operands.
+/// Only called once folding the operation has given up.
+fn error_message(op: OpCode, a: Value, b: Value) -> &'static str {
+    let (ints, numbers) = (a.is_int() && b.is_int(), a.is_numeric() && b.is_numeric());
+    match op {
+        OpCode::Negate if a.is_int() => "Integer overflow.",
+        OpCode::Negate => "Operand must be a number",
+        OpCode::BitNot => "Operand must be an integer.",
+        OpCode::Add if !numbers => "Operands must both be numbers or strings.",
+        OpCode::IntDivide | OpCode::Modulo if ints && b.as_int() == 0 => "Division by zero.",
+        OpCode::IntDivide if !ints => "Operands must be integers.",
+        OpCode::BitAnd | OpCode::BitOr | OpCode::BitXor => "Operands must be integers.",
+        OpCode::ShiftLeft | OpCode::ShiftRight if ints => "Shift amount out of range.",
+        OpCode::ShiftLeft | OpCode::ShiftRight => "Operands must be integers.",
+        _ if ints => "Integer overflow.",
+        _ => "Operands must be numbers.",
+    }
+}
+
+/// Runs register programs. It has no trace support, and compiles source
+/// through the AST, so it never sees the bytecode optimizer's output.
+pub struct RegisterVM {
+    registers: [Value; 256],
+    out: Box<dyn Write>,
+}
+
+impl Default for RegisterVM {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl RegisterVM {
+    pub fn new() -> Self {
+        Self {
+            registers: [Value::NIL; 256],
+            out: Box::new(io::stdout()),
+        }
+    }
+
+    /// Send the values scripts print somewhere other than stdout
+    pub fn set_output(&mut self, out: impl Write + 'static) {
+        self.out = Box::new(out);
+    }
+
+    pub fn interpret(&mut self, source: &str) -> InterpretResult {
+        let Some(script) = parse(source) else {
+            return InterpretResult::CompileError;
+        };
+        match generate(&script) {
+            Ok(program) => self.run(&program),
+            Err(error) => {
+                eprintln!("{}", error);
+                InterpretResult::CompileError
+            }
+        }
+    }
+
+    pub fn run(&mut self, program: &Program) -> InterpretResult {
+        let code = program.code();
+        let constants = program.constants();
+        // Slots are indexed by u8, so the 256 of them need no bounds checks
+        let regs = &mut self.registers;
+        let mut ip = 0;
+
+        macro_rules! runtime_error {
+            ($message:expr) => {{
+                eprintln!("{}", $message);
+                eprintln!("[line {}] in script", program.lines()[ip - 1]);
+                return InterpretResult::RuntimeError;
+            }};
+        }
+
+        // Anything the fast path doesn't handle goes through the folding
+        // code, which has the same semantics as the stack VM
+        macro_rules! slow {
+            ($op:ident, $dst:expr, $a:expr, $b:expr) => {{
+                let (a, b) = (regs[$a as usize], regs[$b as usize]);
+                match fold::binary(OpCode::$op, a, b) {
+                    Some(value) => regs[$dst as usize] = value,
+                    None => runtime_error!(error_message(OpCode::$op, a, b)),
+                }
+            }};
+        }
+
+        macro_rules! compare {
+            ($op:ident, $dst:expr, $a:expr, $b:expr, $cmp:tt) => {{
+                let (a, b) = (regs[$a as usize], regs[$b as usize]);
+                if a.is_int() && b.is_int() {
+                    regs[$dst as usize] = (a.as_int() $cmp b.as_int()).into();
+                } else {
+                    slow!($op, $dst, $a, $b)
+                }
+            }};
+        }
+
+        macro_rules! arithmetic {
+            ($op:ident, $dst:expr, $a:expr, $b:expr, $checked:ident) => {{
+                let (a, b) = (regs[$a as usize], regs[$b as usize]);
+                match (a.is_int() && b.is_int())
+                    .then(|| a.as_int().$checked(b.as_int()))
+                    .flatten()
+                    .map(Value::from)
+                {
+                    Some(value) => regs[$dst as usize] = value,
+                    None => slow!($op, $dst, $a, $b),
+                }
+            }};
+            ($op:ident, $dst:expr, $a:expr, $b:expr, $checked:ident, $float:tt) => {{
+                let (a, b) = (regs[$a as usize], regs[$b as usize]);
+                if a.is_int() && b.is_int() {
+                    arithmetic!($op, $dst, $a, $b, $checked)
+                } else if a.is_numeric() && b.is_numeric() {
+                    regs[$dst as usize] = (a.as_numeric() $float b.as_numeric()).into();
+                } else {
+                    slow!($op, $dst, $a, $b)
+                }
+            }};
+        }
+
+        macro_rules! bitwise {
+            ($op:ident, $dst:expr, $a:expr, $b:expr, $bit:tt) => {{
+                let (a, b) = (regs[$a as usize], regs[$b as usize]);
+                if a.is_int() && b.is_int() {
+                    regs[$dst as usize] = (a.as_int() $bit b.as_int()).into();
+                } else {
+                    slow!($op, $dst, $a, $b)
+                }
+            }};
+        }
+
+        macro_rules! shift {
+            ($op:ident, $dst:expr, $a:expr, $b:expr, $checked:ident) => {{
+                let (a, b) = (regs[$a as usize], regs[$b as usize]);
+                let shifted = (a.is_int() && b.is_int())
+                    .then(|| u32::try_from(b.as_int()).ok())
+                    .flatten()
+                    .and_then(|b| a.as_int().$checked(b));
+                match shifted {
+                    Some(n) => regs[$dst as usize] = Value::from(n),
+                    None => slow!($op, $dst, $a, $b),
+                }
+            }};
+        }
+
+        loop {
+            let instruction = code[ip];
+            ip += 1;
+
+            match instruction {
+                Instruction::Constant { dst, constant } => {
+                    regs[dst as usize] = constants[constant as usize]
+                }
+                Instruction::Nil { dst } => regs[dst as usize] = Value::NIL,
+                Instruction::True { dst } => regs[dst as usize] = true.into(),
+                Instruction::False { dst } => regs[dst as usize] = false.into(),
+                Instruction::Equal { dst, a, b } => {
+                    regs[dst as usize] = (regs[a as usize] == regs[b as usize]).into()
+                }
+                Instruction::NotEqual { dst, a, b } => {
+                    regs[dst as usize] = (regs[a as usize] != regs[b as usize]).into()
+                }
+                Instruction::Greater { dst, a, b } => compare!(Greater, dst, a, b, >),
+                Instruction::GreaterEqual { dst, a, b } => compare!(GreaterEqual, dst, a, b, >=),
+                Instruction::Less { dst, a, b } => compare!(Less, dst, a, b, <),
+                Instruction::LessEqual { dst, a, b } => compare!(LessEqual, dst, a, b, <=),
+                Instruction::Add { dst, a, b } => {
+                    let (x, y) = (regs[a as usize], regs[b as usize]);
+                    if x.is_string() && y.is_string() {
+                        regs[dst as usize] = Obj::concat(x.as_string(), y.as_string()).into();
+                    } else {
+                        arithmetic!(Add, dst, a, b, checked_add, +)
+                    }
+                }
+                Instruction::Subtract { dst, a, b } => {
+                    arithmetic!(Subtract, dst, a, b, checked_sub, -)
+                }
+                Instruction::Multiply { dst, a, b } => {
+                    arithmetic!(Multiply, dst, a, b, checked_mul, *)
+                }
+                Instruction::Divide { dst, a, b } => slow!(Divide, dst, a, b),
+                Instruction::IntDivide { dst, a, b } => {
+                    arithmetic!(IntDivide, dst, a, b, checked_div)
+                }
+                Instruction::Modulo { dst, a, b } => arithmetic!(Modulo, dst, a, b, checked_rem),
+                Instruction::Power { dst, a, b } => slow!(Power, dst, a, b),
+                Instruction::BitAnd { dst, a, b } => bitwise!(BitAnd, dst, a, b, &),
+                Instruction::BitOr { dst, a, b } => bitwise!(BitOr, dst, a, b, |),
+                Instruction::BitXor { dst, a, b } => bitwise!(BitXor, dst, a, b, ^),
+                Instruction::ShiftLeft { dst, a, b } => shift!(ShiftLeft, dst, a, b, checked_shl),
+                Instruction::ShiftRight { dst, a, b } => shift!(ShiftRight, dst, a, b, checked_shr),
+                Instruction::Not { dst, src } => {
+                    regs[dst as usize] = is_falsey(regs[src as usize]).into()
+                }
+                Instruction::Negate { dst, src } => {
+                    let value = regs[src as usize];
+                    match fold::unary(OpCode::Negate, value) {
+                        Some(value) => regs[dst as usize] = value,
+                        None => runtime_error!(error_message(OpCode::Negate, value, value)),
+                    }
+                }
+                Instruction::BitNot { dst, src } => {
+                    let value = regs[src as usize];
+                    match fold::unary(OpCode::BitNot, value) {
+                        Some(value) => regs[dst as usize] = value,
+                        None => runtime_error!(error_message(OpCode::BitNot, value, value)),
+                    }
+                }
+                Instruction::Jump { target } => ip = target as usize,
+                Instruction::JumpIfFalse { src, target } => {
+                    if is_falsey(regs[src as usize]) {
+                        ip = target as usize;
+                    }
+                }
+                Instruction::JumpIfNotNil { src, target } => {
+                    if !regs[src as usize].is_nil() {
+                        ip = target as usize;
+                    }
+                }
+                Instruction::Return { src } => {
+                    let _ = writeln!(self.out, "{}", regs[src as usize]);
+                    return InterpretResult::Ok;
+                }
+            }
+        }
+    }
+}
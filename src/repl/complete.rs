@@ -0,0 +1,86 @@
+use crate::scanner::KEYWORDS;
+
+/// Candidates for the word ending at the cursor
+#[derive(Debug, PartialEq, Eq)]
+pub struct Completion {
+    /// Where the word being completed starts in the line
+    pub start: usize,
+    pub candidates: Vec<String>,
+}
+
+fn is_word(c: char) -> bool {
+    c.is_ascii_alphanumeric() || c == '_'
+}
+
+/// Complete the identifier before `cursor` in `line`. Only keywords are
+/// offered: the language has no globals or fields to draw on yet, so a
+/// word after a `.` gets no candidates.
+pub fn complete(line: &str, cursor: usize) -> Completion {
+    let before = &line[..cursor];
+    let start = before
+        .char_indices()
+        .rev()
+        .take_while(|(_, c)| is_word(*c))
+        .last()
+        .map_or(cursor, |(i, _)| i);
+    let prefix = &before[start..];
+
+    let starts_number = prefix.starts_with(|c: char| c.is_ascii_digit());
+    let after_dot = before[..start].ends_with('.');
+    let mut candidates: Vec<String> = if prefix.is_empty() || starts_number || after_dot {
+        Vec::new()
+    } else {
+        KEYWORDS
+            .iter()
+            .filter(|keyword| keyword.starts_with(prefix))
+            .map(|keyword| (*keyword).to_owned())
+            .collect()
+    };
+    candidates.sort();
+
+    Completion { start, candidates }
+}
+
+/// The longest prefix shared by all of `words`
+pub fn common_prefix(words: &[String]) -> &str {
+    let Some(first) = words.first() else {
+        return "";
+    };
+    let len = words[1..].iter().fold(first.len(), |len, word| {
+        first[..len]
+            .bytes()
+            .zip(word.bytes())
+            .take_while(|(a, b)| a == b)
+            .count()
+    });
+    &first[..len]
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_complete() {
+        assert_eq!(
+            complete("1 + fa", 6),
+            Completion {
+                start: 4,
+                candidates: vec!["false".to_owned()]
+            }
+        );
+        assert_eq!(complete("f x", 1).candidates, vec!["false", "for", "fun"]);
+        assert!(complete("a.fa", 4).candidates.is_empty());
+        assert!(complete("1 + ", 4).candidates.is_empty());
+    }
+
+    #[test]
+    fn test_common_prefix() {
+        let words = |w: &[&str]| w.iter().map(|s| s.to_string()).collect::<Vec<_>>();
+
+        assert_eq!(common_prefix(&words(&["this", "true"])), "t");
+        assert_eq!(common_prefix(&words(&["false", "for", "fun"])), "f");
+        assert_eq!(common_prefix(&words(&["print"])), "print");
+        assert_eq!(common_prefix(&[]), "");
+    }
+}
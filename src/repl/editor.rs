@@ -0,0 +1,433 @@
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufRead, IsTerminal, Read, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+use super::complete::{common_prefix, complete};
+
+const MAX_HISTORY: usize = 1000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Key {
+    Char(char),
+    Enter,
+    Backspace,
+    Delete,
+    Left,
+    Right,
+    Up,
+    Down,
+    Home,
+    End,
+    Interrupt,
+    EndOfFile,
+    Tab,
+    Other,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+enum Action {
+    Continue,
+    Accept,
+    Cancel,
+    EndOfFile,
+    ShowCandidates(Vec<String>),
+}
+
+/// What reading a line produced
+#[derive(Debug, PartialEq, Eq)]
+pub enum Input {
+    Line(String),
+    /// The user pressed Ctrl-C
+    Cancel,
+    EndOfFile,
+}
+
+/// The line being edited and where the cursor is in it
+#[derive(Debug, Default)]
+struct LineBuffer {
+    chars: Vec<char>,
+    cursor: usize,
+}
+
+impl LineBuffer {
+    fn set(&mut self, text: &str) {
+        self.chars = text.chars().collect();
+        self.cursor = self.chars.len();
+    }
+
+    fn text(&self) -> String {
+        self.chars.iter().collect()
+    }
+}
+
+/// Reads lines from the terminal with cursor movement and history. When
+/// stdin isn't a terminal, or it can't be switched into raw mode with
+/// `stty`, lines are read as-is.
+pub struct LineEditor {
+    history: Vec<String>,
+    // Position while browsing history, and the line being edited before
+    browsing: Option<(usize, String)>,
+    history_file: Option<PathBuf>,
+    buffer: LineBuffer,
+}
+
+impl LineEditor {
+    pub fn new(history_file: Option<PathBuf>) -> Self {
+        let mut history: Vec<String> = history_file
+            .as_ref()
+            .and_then(|path| fs::read_to_string(path).ok())
+            .map(|text| text.lines().map(str::to_owned).collect())
+            .unwrap_or_default();
+
+        // Trim a file that grew past the limit, e.g. from older versions
+        let excess = history.len().saturating_sub(MAX_HISTORY);
+        if excess > 0 {
+            history.drain(..excess);
+            if let Some(path) = &history_file {
+                let _ = write_history(path, &history);
+            }
+        }
+
+        Self {
+            history,
+            browsing: None,
+            history_file,
+            buffer: LineBuffer::default(),
+        }
+    }
+
+    pub fn add_history(&mut self, line: &str) {
+        if line.trim().is_empty() || self.history.last().map(String::as_str) == Some(line) {
+            return;
+        }
+        self.history.push(line.to_owned());
+        let full = self.history.len() > MAX_HISTORY;
+        if full {
+            self.history.remove(0);
+        }
+
+        if let Some(path) = &self.history_file {
+            // History is a convenience, so failing to save it isn't an error.
+            // Once it's full the file is rewritten so it doesn't grow forever.
+            if full {
+                let _ = write_history(path, &self.history);
+            } else if let Ok(mut file) = OpenOptions::new().create(true).append(true).open(path) {
+                let _ = writeln!(file, "{}", line);
+            }
+        }
+    }
+
+    /// Read one line, or find out why there isn't one
+    pub fn read_line(&mut self, prompt: &str) -> io::Result<Input> {
+        if io::stdin().is_terminal() {
+            if let Some(_raw) = RawMode::enable() {
+                return self.read_raw(prompt);
+            }
+        }
+
+        print!("{}", prompt);
+        io::stdout().flush()?;
+        let mut line = String::new();
+        if io::stdin().lock().read_line(&mut line)? == 0 {
+            println!();
+            return Ok(Input::EndOfFile);
+        }
+        Ok(Input::Line(line.trim_end_matches(['\n', '\r']).to_owned()))
+    }
+
+    fn read_raw(&mut self, prompt: &str) -> io::Result<Input> {
+        let mut stdin = io::stdin().lock();
+        let mut stdout = io::stdout().lock();
+        self.buffer = LineBuffer::default();
+        self.browsing = None;
+        redraw(&mut stdout, prompt, &self.buffer)?;
+
+        loop {
+            let key = read_key(&mut stdin)?;
+            let action = self.handle_key(key);
+            match action {
+                Action::Continue => redraw(&mut stdout, prompt, &self.buffer)?,
+                Action::ShowCandidates(candidates) => {
+                    write!(stdout, "\r\n{}\r\n", candidates.join("  "))?;
+                    redraw(&mut stdout, prompt, &self.buffer)?;
+                }
+                Action::Accept => {
+                    write!(stdout, "\r\n")?;
+                    return Ok(Input::Line(self.buffer.text()));
+                }
+                Action::Cancel => {
+                    write!(stdout, "^C\r\n")?;
+                    return Ok(Input::Cancel);
+                }
+                Action::EndOfFile => {
+                    write!(stdout, "\r\n")?;
+                    return Ok(Input::EndOfFile);
+                }
+            }
+        }
+    }
+
+    fn handle_key(&mut self, key: Key) -> Action {
+        let buffer = &mut self.buffer;
+        match key {
+            Key::Char(c) => {
+                buffer.chars.insert(buffer.cursor, c);
+                buffer.cursor += 1;
+            }
+            Key::Tab => return self.complete(),
+            Key::Backspace if buffer.cursor > 0 => {
+                buffer.cursor -= 1;
+                buffer.chars.remove(buffer.cursor);
+            }
+            Key::Delete if buffer.cursor < buffer.chars.len() => {
+                buffer.chars.remove(buffer.cursor);
+            }
+            Key::Left if buffer.cursor > 0 => buffer.cursor -= 1,
+            Key::Right if buffer.cursor < buffer.chars.len() => buffer.cursor += 1,
+            Key::Home => buffer.cursor = 0,
+            Key::End => buffer.cursor = buffer.chars.len(),
+            Key::Up => self.history_step(-1),
+            Key::Down => self.history_step(1),
+            Key::Enter => return Action::Accept,
+            Key::Interrupt => return Action::Cancel,
+            Key::EndOfFile if buffer.chars.is_empty() => return Action::EndOfFile,
+            _ => {}
+        }
+        Action::Continue
+    }
+
+    /// Insert as much of the word at the cursor as all candidates agree on,
+    /// or list them when that adds nothing
+    fn complete(&mut self) -> Action {
+        let line = self.buffer.text();
+        let cursor = self.buffer.chars[..self.buffer.cursor]
+            .iter()
+            .collect::<String>()
+            .len();
+        let completion = complete(&line, cursor);
+
+        let typed = cursor - completion.start;
+        let shared = common_prefix(&completion.candidates);
+        if shared.len() > typed {
+            for c in shared[typed..].chars() {
+                self.buffer.chars.insert(self.buffer.cursor, c);
+                self.buffer.cursor += 1;
+            }
+            if completion.candidates.len() == 1 {
+                self.buffer.chars.insert(self.buffer.cursor, ' ');
+                self.buffer.cursor += 1;
+            }
+            Action::Continue
+        } else if completion.candidates.len() > 1 {
+            Action::ShowCandidates(completion.candidates)
+        } else {
+            Action::Continue
+        }
+    }
+
+    fn history_step(&mut self, step: isize) {
+        let (index, editing) = match self.browsing.take() {
+            Some(browsing) => browsing,
+            None => (self.history.len(), self.buffer.text()),
+        };
+        let index = index.saturating_add_signed(step).min(self.history.len());
+
+        if index == self.history.len() {
+            self.buffer.set(&editing);
+        } else {
+            self.buffer.set(&self.history[index]);
+            self.browsing = Some((index, editing));
+        }
+    }
+}
+
+fn write_history(path: &Path, history: &[String]) -> io::Result<()> {
+    let mut text = history.join("\n");
+    text.push('\n');
+    fs::write(path, text)
+}
+
+fn redraw(out: &mut impl Write, prompt: &str, buffer: &LineBuffer) -> io::Result<()> {
+    // Return to the start of the line, rewrite it, clear anything left over
+    // and move the cursor back into place
+    write!(out, "\r{}{}\x1b[K", prompt, buffer.text())?;
+    let back = buffer.chars.len() - buffer.cursor;
+    if back > 0 {
+        write!(out, "\x1b[{}D", back)?;
+    }
+    out.flush()
+}
+
+fn read_byte(input: &mut impl Read) -> io::Result<Option<u8>> {
+    let mut byte = [0];
+    match input.read(&mut byte)? {
+        0 => Ok(None),
+        _ => Ok(Some(byte[0])),
+    }
+}
+
+fn read_key(input: &mut impl Read) -> io::Result<Key> {
+    let Some(byte) = read_byte(input)? else {
+        return Ok(Key::EndOfFile);
+    };
+
+    Ok(match byte {
+        b'\r' | b'\n' => Key::Enter,
+        0x7f | 0x08 => Key::Backspace,
+        b'\t' => Key::Tab,
+        0x01 => Key::Home,
+        0x03 => Key::Interrupt,
+        0x04 => Key::EndOfFile,
+        0x05 => Key::End,
+        0x1b => read_escape(input)?,
+        byte if byte.is_ascii() && !byte.is_ascii_control() => Key::Char(byte as char),
+        _ => Key::Other,
+    })
+}
+
+fn read_escape(input: &mut impl Read) -> io::Result<Key> {
+    if read_byte(input)? != Some(b'[') {
+        return Ok(Key::Other);
+    }
+
+    Ok(match read_byte(input)? {
+        Some(b'A') => Key::Up,
+        Some(b'B') => Key::Down,
+        Some(b'C') => Key::Right,
+        Some(b'D') => Key::Left,
+        Some(b'H') => Key::Home,
+        Some(b'F') => Key::End,
+        Some(b'3') if read_byte(input)? == Some(b'~') => Key::Delete,
+        _ => Key::Other,
+    })
+}
+
+/// Puts the terminal into raw mode until dropped
+struct RawMode {
+    saved: String,
+}
+
+impl RawMode {
+    fn enable() -> Option<Self> {
+        let output = stty(&["-g"])?;
+        let saved = String::from_utf8(output).ok()?.trim().to_owned();
+        stty(&["-icanon", "-echo", "-isig", "-ixon", "min", "1"])?;
+        Some(Self { saved })
+    }
+}
+
+impl Drop for RawMode {
+    fn drop(&mut self) {
+        stty(&[&self.saved]);
+    }
+}
+
+fn stty(args: &[&str]) -> Option<Vec<u8>> {
+    let tty = File::open("/dev/tty").ok()?;
+    let output = Command::new("stty")
+        .args(args)
+        .stdin(Stdio::from(tty))
+        .stderr(Stdio::null())
+        .output()
+        .ok()?;
+    output.status.success().then_some(output.stdout)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn type_keys(editor: &mut LineEditor, keys: &[Key]) -> Vec<Action> {
+        keys.iter().map(|key| editor.handle_key(*key)).collect()
+    }
+
+    #[test]
+    fn test_editing() {
+        let mut editor = LineEditor::new(None);
+        type_keys(
+            &mut editor,
+            &[
+                Key::Char('1'),
+                Key::Char('2'),
+                Key::Left,
+                Key::Char('+'),
+                Key::End,
+                Key::Backspace,
+                Key::Home,
+                Key::Delete,
+            ],
+        );
+        assert_eq!(editor.buffer.text(), "+");
+        assert_eq!(editor.handle_key(Key::EndOfFile), Action::Continue);
+        assert_eq!(editor.handle_key(Key::Enter), Action::Accept);
+
+        editor.buffer = LineBuffer::default();
+        assert_eq!(editor.handle_key(Key::EndOfFile), Action::EndOfFile);
+    }
+
+    #[test]
+    fn test_tab_completion() {
+        let mut editor = LineEditor::new(None);
+        editor.buffer.set("1 ?? ni");
+        assert_eq!(editor.handle_key(Key::Tab), Action::Continue);
+        assert_eq!(editor.buffer.text(), "1 ?? nil ");
+
+        editor.buffer.set("t");
+        assert_eq!(
+            editor.handle_key(Key::Tab),
+            Action::ShowCandidates(vec!["this".to_owned(), "true".to_owned()])
+        );
+        assert_eq!(editor.buffer.text(), "t");
+    }
+
+    #[test]
+    fn test_history() {
+        let mut editor = LineEditor::new(None);
+        editor.add_history("1");
+        editor.add_history("2");
+        editor.add_history("2");
+        editor.buffer.set("draft");
+
+        type_keys(&mut editor, &[Key::Up]);
+        assert_eq!(editor.buffer.text(), "2");
+        type_keys(&mut editor, &[Key::Up, Key::Up]);
+        assert_eq!(editor.buffer.text(), "1");
+        type_keys(&mut editor, &[Key::Down]);
+        assert_eq!(editor.buffer.text(), "2");
+        type_keys(&mut editor, &[Key::Down]);
+        assert_eq!(editor.buffer.text(), "draft");
+    }
+
+    #[test]
+    fn test_history_file_is_capped() {
+        let path = std::env::temp_dir().join(format!("rlox_history_{}", std::process::id()));
+        let lines: Vec<_> = (0..MAX_HISTORY + 5).map(|i| i.to_string()).collect();
+        write_history(&path, &lines).unwrap();
+        let saved = || fs::read_to_string(&path).unwrap();
+
+        let mut editor = LineEditor::new(Some(path.clone()));
+        assert_eq!(editor.history, lines[5..]);
+        assert_eq!(saved().lines().count(), MAX_HISTORY);
+        assert_eq!(saved().lines().next(), Some("5"));
+
+        editor.add_history("new");
+        assert_eq!(editor.history.len(), MAX_HISTORY);
+        assert_eq!(saved().lines().count(), MAX_HISTORY);
+        assert_eq!(saved().lines().next(), Some("6"));
+        assert_eq!(saved().lines().last(), Some("new"));
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_escape_sequences() {
+        let mut input: &[u8] = b"\x1b[A\x1b[3~a\x7f";
+
+        assert_eq!(read_key(&mut input).unwrap(), Key::Up);
+        assert_eq!(read_key(&mut input).unwrap(), Key::Delete);
+        assert_eq!(read_key(&mut input).unwrap(), Key::Char('a'));
+        assert_eq!(read_key(&mut input).unwrap(), Key::Backspace);
+        assert_eq!(read_key(&mut input).unwrap(), Key::EndOfFile);
+    }
+}
@@ -0,0 +1,229 @@
+mod complete;
+mod editor;
+
+use std::env;
+use std::path::PathBuf;
+
+use crate::chunk::Chunk;
+use crate::compiler::compile;
+use crate::debug::write_listing;
+use crate::optimizer::OptLevel;
+use crate::scanner::{Scanner, TokenType};
+use crate::vm::VM;
+
+pub use complete::{complete, Completion};
+pub use editor::{Input, LineEditor};
+
+const HELP: &str = "Enter an expression to evaluate it and print the result.
+Input continues onto the next line while brackets or a string are left open.
+Tab completes keywords.
+
+  :help            Print this message
+  :quit            Leave the REPL (so does Ctrl-D)
+  :reset           Discard any pending input and start over with a fresh VM
+  :disasm <expr>   Print the bytecode an expression compiles to";
+
+/// Where the REPL keeps its history between sessions
+pub fn history_path() -> Option<PathBuf> {
+    env::var_os("HOME").map(|home| PathBuf::from(home).join(".rlox_history"))
+}
+
+/// Whether `source` ends partway through something that the next line could
+/// finish: an open bracket, a string or a block comment
+pub fn is_incomplete(source: &str) -> bool {
+    let mut scanner = Scanner::new(source);
+    let mut depth = 0;
+    loop {
+        let token = scanner.scan_token();
+        match token.token_type {
+            TokenType::LeftParen | TokenType::LeftBrace => depth += 1,
+            TokenType::RightParen | TokenType::RightBrace => depth -= 1,
+            TokenType::Error => {
+                return matches!(
+                    token.slice,
+                    "Unterminated string." | "Unterminated block comment."
+                )
+            }
+            TokenType::EOF => return depth > 0,
+            _ => {}
+        }
+    }
+}
+
+enum Meta<'a> {
+    Help,
+    Quit,
+    Reset,
+    Disasm(&'a str),
+}
+
+fn parse_meta(line: &str) -> Result<Meta<'_>, String> {
+    let (command, rest) = line.split_once(' ').unwrap_or((line, ""));
+    match (command, rest.trim()) {
+        (":help" | ":h", "") => Ok(Meta::Help),
+        (":quit" | ":q", "") => Ok(Meta::Quit),
+        (":reset", "") => Ok(Meta::Reset),
+        (":disasm", "") => Err("Expect an expression after :disasm.".to_owned()),
+        (":disasm", source) => Ok(Meta::Disasm(source)),
+        _ => Err(format!("Unknown command '{}'. Try :help.", line.trim())),
+    }
+}
+
+fn disassemble(source: &str, level: OptLevel) {
+    let mut chunk = Chunk::new();
+    if !compile(source, &mut chunk) {
+        return;
+    }
+    match level.apply(chunk) {
+        Ok(chunk) => {
+            let mut listing = String::new();
+            write_listing(&mut listing, &chunk, "repl", None).unwrap();
+            print!("{}", listing);
+        }
+        Err(error) => eprintln!("Could not optimize: {}", error),
+    }
+}
+
+/// Input read so far that the next line could still complete
+#[derive(Default)]
+struct Session {
+    pending: String,
+}
+
+impl Session {
+    fn prompt(&self) -> &'static str {
+        if self.pending.is_empty() {
+            "> "
+        } else {
+            "... "
+        }
+    }
+
+    /// Drop any unfinished input, as on Ctrl-C
+    fn cancel(&mut self) {
+        self.pending.clear();
+    }
+
+    /// Handle one line: a meta command, or source to run once it's complete.
+    /// Returns false when the user asks to quit.
+    fn feed(&mut self, vm: &mut VM, line: &str) -> bool {
+        if line.trim_start().starts_with(':') {
+            match parse_meta(line.trim()) {
+                Ok(Meta::Help) => println!("{}", HELP),
+                Ok(Meta::Quit) => return false,
+                Ok(Meta::Reset) => {
+                    self.pending.clear();
+                    let (trace, level) = (vm.trace(), vm.opt_level());
+                    *vm = VM::new();
+                    vm.set_trace(trace);
+                    vm.set_opt_level(level);
+                }
+                Ok(Meta::Disasm(source)) if source.is_ascii() => {
+                    disassemble(source, vm.opt_level())
+                }
+                Ok(Meta::Disasm(_)) => eprintln!("Source must be ASCII."),
+                Err(error) => eprintln!("{}", error),
+            }
+            return true;
+        }
+
+        if !line.is_ascii() {
+            eprintln!("Source must be ASCII.");
+            self.pending.clear();
+            return true;
+        }
+        self.pending.push_str(line);
+        self.pending.push('\n');
+        if is_incomplete(&self.pending) {
+            return true;
+        }
+
+        if !self.pending.trim().is_empty() {
+            vm.interpret(&self.pending);
+        }
+        self.pending.clear();
+        true
+    }
+}
+
+/// Run an interactive session on `vm` until the user quits or input ends
+pub fn run(vm: &mut VM) {
+    let mut editor = LineEditor::new(history_path());
+    let mut session = Session::default();
+
+    loop {
+        match editor.read_line(session.prompt()) {
+            Ok(Input::Line(line)) => {
+                editor.add_history(&line);
+                if !session.feed(vm, &line) {
+                    break;
+                }
+            }
+            Ok(Input::Cancel) => session.cancel(),
+            Ok(Input::EndOfFile) => break,
+            Err(error) => {
+                eprintln!("Could not read input: {}", error);
+                break;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::testing::Shared;
+
+    #[test]
+    fn test_is_incomplete() {
+        assert!(!is_incomplete("1 + 2\n"));
+        assert!(is_incomplete("(1 +\n"));
+        assert!(is_incomplete("((1 + 2)\n"));
+        assert!(is_incomplete("\"two\nlines"));
+        assert!(is_incomplete("1 /* still\n"));
+        assert!(!is_incomplete("(1 + 2))\n"));
+        assert!(!is_incomplete("1 $ 2\n"));
+    }
+
+    #[test]
+    fn test_parse_meta() {
+        assert!(matches!(parse_meta(":q"), Ok(Meta::Quit)));
+        assert!(matches!(
+            parse_meta(":disasm 1 + 2"),
+            Ok(Meta::Disasm("1 + 2"))
+        ));
+        assert!(parse_meta(":disasm").is_err());
+        assert!(parse_meta(":quit now").is_err());
+        assert!(parse_meta(":frobnicate").is_err());
+    }
+
+    #[test]
+    fn test_meta_commands_while_pending() {
+        let mut vm = VM::new();
+        let mut session = Session::default();
+
+        assert!(session.feed(&mut vm, "(1 +"));
+        assert_eq!(session.prompt(), "... ");
+        assert!(session.feed(&mut vm, ":help"));
+        assert_eq!(session.pending, "(1 +\n");
+        assert!(session.feed(&mut vm, ":reset"));
+        assert_eq!(session.prompt(), "> ");
+
+        assert!(session.feed(&mut vm, "(1 +"));
+        assert!(!session.feed(&mut vm, ":quit"));
+    }
+
+    #[test]
+    fn test_cancel_discards_pending_input() {
+        let out = Shared::default();
+        let mut vm = VM::new();
+        vm.set_output(out.clone());
+        let mut session = Session::default();
+
+        assert!(session.feed(&mut vm, "(1 +"));
+        session.cancel();
+        assert_eq!(session.prompt(), "> ");
+        assert!(session.feed(&mut vm, "2"));
+        assert_eq!(out.take(), "2\n");
+    }
+}
@@ -57,7 +57,7 @@ impl<'a> Scanner<'a> {
         }
     }
 
-    fn skip_whitespace(&mut self) {
+    fn skip_whitespace(&mut self) -> Result<(), &'static str> {
         loop {
             match self.source.peek() {
                 b' ' | b'\t' | b'\r' => {
@@ -67,21 +67,53 @@ impl<'a> Scanner<'a> {
                     self.line += 1;
                     self.source.advance();
                 }
-                b'/' => {
-                    if self.source.peek_next() != b'/' {
-                        return;
-                    }
-                    while self.source.peek() != b'\n' && !self.source.is_at_end() {
-                        self.source.advance();
+                b'/' => match self.source.peek_next() {
+                    b'/' => {
+                        while self.source.peek() != b'\n' && !self.source.is_at_end() {
+                            self.source.advance();
+                        }
                     }
+                    b'*' => self.block_comment()?,
+                    _ => return Ok(()),
+                },
+                _ => return Ok(()),
+            }
+        }
+    }
+
+    fn block_comment(&mut self) -> Result<(), &'static str> {
+        // Consume the opening /*
+        self.source.advance();
+        self.source.advance();
+
+        let mut depth = 1;
+        while depth > 0 {
+            if self.source.is_at_end() {
+                return Err("Unterminated block comment.");
+            }
+
+            match (self.source.peek(), self.source.peek_next()) {
+                (b'/', b'*') => {
+                    depth += 1;
+                    self.source.advance();
+                }
+                (b'*', b'/') => {
+                    depth -= 1;
+                    self.source.advance();
                 }
-                _ => break,
+                (b'\n', _) => self.line += 1,
+                _ => {}
             }
+            self.source.advance();
         }
+
+        Ok(())
     }
 
     pub fn scan_token(&mut self) -> Token<'a> {
-        self.skip_whitespace();
+        if let Err(message) = self.skip_whitespace() {
+            return self.error_token(message);
+        }
         self.source.reset();
 
         if self.source.is_at_end() {
@@ -103,12 +135,64 @@ impl<'a> Scanner<'a> {
             b'}' => self.make_token(TokenType::RightBrace),
             b',' => self.make_token(TokenType::Comma),
             b'.' => self.make_token(TokenType::Dot),
-            b'-' => self.make_token(TokenType::Minus),
-            b'+' => self.make_token(TokenType::Plus),
             b';' => self.make_token(TokenType::Semicolon),
-            b'/' => self.make_token(TokenType::Slash),
-            b'*' => self.make_token(TokenType::Star),
+            b':' => self.make_token(TokenType::Colon),
+            b'&' => self.make_token(TokenType::Ampersand),
+            b'|' => self.make_token(TokenType::Pipe),
+            b'^' => self.make_token(TokenType::Caret),
+            b'~' => self.make_token(TokenType::Tilde),
 
+            b'-' => {
+                let token_type = if self.source.match_char(b'=') {
+                    TokenType::MinusEqual
+                } else {
+                    TokenType::Minus
+                };
+                self.make_token(token_type)
+            }
+            b'+' => {
+                let token_type = if self.source.match_char(b'=') {
+                    TokenType::PlusEqual
+                } else {
+                    TokenType::Plus
+                };
+                self.make_token(token_type)
+            }
+            b'/' => {
+                let token_type = if self.source.match_char(b'=') {
+                    TokenType::SlashEqual
+                } else {
+                    TokenType::Slash
+                };
+                self.make_token(token_type)
+            }
+            b'*' => {
+                let token_type = if self.source.match_char(b'=') {
+                    TokenType::StarEqual
+                } else if self.source.match_char(b'*') {
+                    TokenType::StarStar
+                } else {
+                    TokenType::Star
+                };
+                self.make_token(token_type)
+            }
+            b'\\' => self.make_token(TokenType::Backslash),
+            b'%' => {
+                let token_type = if self.source.match_char(b'=') {
+                    TokenType::PercentEqual
+                } else {
+                    TokenType::Percent
+                };
+                self.make_token(token_type)
+            }
+            b'?' => {
+                let token_type = if self.source.match_char(b'?') {
+                    TokenType::QuestionQuestion
+                } else {
+                    TokenType::Question
+                };
+                self.make_token(token_type)
+            }
             b'!' => {
                 let token_type = if self.source.match_char(b'=') {
                     TokenType::BangEqual
@@ -128,6 +212,8 @@ impl<'a> Scanner<'a> {
             b'<' => {
                 let token_type = if self.source.match_char(b'=') {
                     TokenType::LessEqual
+                } else if self.source.match_char(b'<') {
+                    TokenType::LessLess
                 } else {
                     TokenType::Less
                 };
@@ -136,6 +222,8 @@ impl<'a> Scanner<'a> {
             b'>' => {
                 let token_type = if self.source.match_char(b'=') {
                     TokenType::GreaterEqual
+                } else if self.source.match_char(b'>') {
+                    TokenType::GreaterGreater
                 } else {
                     TokenType::Greater
                 };
@@ -149,7 +237,7 @@ impl<'a> Scanner<'a> {
     }
 
     fn string(&mut self) -> Token<'a> {
-        while self.source.peek() != b'"' {
+        while self.source.peek() != b'"' && !self.source.is_at_end() {
             if self.source.peek() == b'\n' {
                 self.line += 1;
             }
@@ -184,7 +272,7 @@ impl<'a> Scanner<'a> {
             self.source.advance();
         }
 
-        return self.make_token(self.identifier_type());
+        self.make_token(self.identifier_type())
     }
 
     fn identifier_type(&self) -> TokenType {
@@ -230,6 +318,12 @@ impl<'a> Scanner<'a> {
     }
 }
 
+/// Every reserved word, in the order `identifier_type` checks them
+pub const KEYWORDS: &[&str] = &[
+    "and", "class", "else", "if", "nil", "or", "print", "return", "super", "var", "while", "false",
+    "for", "fun", "this", "true",
+];
+
 fn check_keyword(identifier: &[u8], rest: &str, token_type: TokenType) -> TokenType {
     if identifier == rest.as_bytes() {
         token_type
@@ -263,6 +357,66 @@ mod test {
         assert_eq!(scanner.scan_token().token_type, TokenType::EOF);
     }
 
+    #[test]
+    fn test_block_comment() {
+        let mut scanner = Scanner::new("/* this is\na comment */ (");
+
+        assert_eq!(
+            scanner.scan_token(),
+            Token {
+                token_type: TokenType::LeftParen,
+                line: 2,
+                slice: "(",
+            }
+        );
+        assert_eq!(scanner.scan_token().token_type, TokenType::EOF);
+    }
+
+    #[test]
+    fn test_nested_block_comment() {
+        let mut scanner = Scanner::new("/* outer /* inner */ // still\n outer */ )");
+
+        assert_eq!(scanner.scan_token().token_type, TokenType::RightParen);
+        assert_eq!(scanner.scan_token().token_type, TokenType::EOF);
+    }
+
+    #[test]
+    fn test_unterminated_block_comment() {
+        let mut scanner = Scanner::new("/* outer /* inner */\n");
+
+        assert_eq!(
+            scanner.scan_token(),
+            Token {
+                token_type: TokenType::Error,
+                line: 2,
+                slice: "Unterminated block comment.",
+            }
+        );
+    }
+
+    #[test]
+    fn test_unterminated_string() {
+        let mut scanner = Scanner::new("\"never\nclosed");
+
+        assert_eq!(
+            scanner.scan_token(),
+            Token {
+                token_type: TokenType::Error,
+                line: 2,
+                slice: "Unterminated string.",
+            }
+        );
+    }
+
+    #[test]
+    fn test_keywords() {
+        for keyword in KEYWORDS {
+            let token = Scanner::new(keyword).scan_token();
+            assert_ne!(token.token_type, TokenType::Identifier, "{}", keyword);
+            assert_eq!(token.slice, *keyword);
+        }
+    }
+
     #[test]
     fn test_single_token() {
         let mut scanner = Scanner::new("(");
@@ -291,6 +445,51 @@ mod test {
         assert_eq!(scanner.scan_token().token_type, TokenType::EOF);
     }
 
+    #[test]
+    fn test_arithmetic_tokens() {
+        let mut scanner = Scanner::new("+ += - -= * *= ** / /= % %= \\");
+
+        assert_eq!(scanner.scan_token().token_type, TokenType::Plus);
+        assert_eq!(scanner.scan_token().token_type, TokenType::PlusEqual);
+        assert_eq!(scanner.scan_token().token_type, TokenType::Minus);
+        assert_eq!(scanner.scan_token().token_type, TokenType::MinusEqual);
+        assert_eq!(scanner.scan_token().token_type, TokenType::Star);
+        assert_eq!(scanner.scan_token().token_type, TokenType::StarEqual);
+        assert_eq!(scanner.scan_token().token_type, TokenType::StarStar);
+        assert_eq!(scanner.scan_token().token_type, TokenType::Slash);
+        assert_eq!(scanner.scan_token().token_type, TokenType::SlashEqual);
+        assert_eq!(scanner.scan_token().token_type, TokenType::Percent);
+        assert_eq!(scanner.scan_token().token_type, TokenType::PercentEqual);
+        assert_eq!(scanner.scan_token().token_type, TokenType::Backslash);
+        assert_eq!(scanner.scan_token().token_type, TokenType::EOF);
+    }
+
+    #[test]
+    fn test_bitwise_tokens() {
+        let mut scanner = Scanner::new("% & | ^ ~ << >> <= >");
+
+        assert_eq!(scanner.scan_token().token_type, TokenType::Percent);
+        assert_eq!(scanner.scan_token().token_type, TokenType::Ampersand);
+        assert_eq!(scanner.scan_token().token_type, TokenType::Pipe);
+        assert_eq!(scanner.scan_token().token_type, TokenType::Caret);
+        assert_eq!(scanner.scan_token().token_type, TokenType::Tilde);
+        assert_eq!(scanner.scan_token().token_type, TokenType::LessLess);
+        assert_eq!(scanner.scan_token().token_type, TokenType::GreaterGreater);
+        assert_eq!(scanner.scan_token().token_type, TokenType::LessEqual);
+        assert_eq!(scanner.scan_token().token_type, TokenType::Greater);
+        assert_eq!(scanner.scan_token().token_type, TokenType::EOF);
+    }
+
+    #[test]
+    fn test_conditional_tokens() {
+        let mut scanner = Scanner::new("? : ??");
+
+        assert_eq!(scanner.scan_token().token_type, TokenType::Question);
+        assert_eq!(scanner.scan_token().token_type, TokenType::Colon);
+        assert_eq!(scanner.scan_token().token_type, TokenType::QuestionQuestion);
+        assert_eq!(scanner.scan_token().token_type, TokenType::EOF);
+    }
+
     #[test]
     fn test_string() {
         let mut scanner = Scanner::new("\"abc\"");
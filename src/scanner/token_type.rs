@@ -8,21 +8,38 @@ pub enum TokenType {
     RightBrace,
     Comma,
     Dot,
+    Semicolon,
+    Colon,
+    Ampersand,
+    Pipe,
+    Caret,
+    Tilde,
+    Backslash,
+
+    // One or two char tokens
     Minus,
+    MinusEqual,
     Plus,
-    Semicolon,
+    PlusEqual,
     Slash,
+    SlashEqual,
     Star,
-
-    // One or two char tokens
+    StarEqual,
+    StarStar,
+    Percent,
+    PercentEqual,
+    Question,
+    QuestionQuestion,
     Bang,
     BangEqual,
     Equal,
     EqualEqual,
     Greater,
     GreaterEqual,
+    GreaterGreater,
     Less,
     LessEqual,
+    LessLess,
 
     // Literals
     Identifier,
@@ -0,0 +1,260 @@
+use std::fmt::Display;
+use std::ptr::copy_nonoverlapping;
+use std::str;
+
+use crate::chunk::Chunk;
+use crate::memory::allocate;
+use crate::object::Obj;
+use crate::value::Value;
+use crate::verify::{verify, VerifyError};
+
+// A .loxc file is the magic bytes, a little-endian u16 format version and
+// a u32 checksum of everything after the header. The body holds the code
+// length, the code, one line number per code byte and the constant table.
+pub const MAGIC: &[u8; 4] = b"LOXC";
+pub const VERSION: u16 = 3;
+const HEADER_LEN: usize = 10;
+
+const TAG_NIL: u8 = 0;
+const TAG_BOOL: u8 = 1;
+const TAG_NUMBER: u8 = 2;
+const TAG_INT: u8 = 3;
+const TAG_STRING: u8 = 4;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LoadError {
+    BadMagic,
+    UnsupportedVersion(u16),
+    ChecksumMismatch,
+    Truncated,
+    TrailingBytes,
+    InvalidConstantTag(u8),
+    InvalidString,
+    Invalid(VerifyError),
+}
+
+impl Display for LoadError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::BadMagic => write!(f, "Not a compiled Lox file."),
+            Self::UnsupportedVersion(v) => write!(f, "Unsupported bytecode version {}.", v),
+            Self::ChecksumMismatch => write!(f, "Checksum mismatch, file is corrupt."),
+            Self::Truncated => write!(f, "Unexpected end of file."),
+            Self::TrailingBytes => write!(f, "Unexpected data after end of chunk."),
+            Self::InvalidConstantTag(tag) => write!(f, "Invalid constant tag {}.", tag),
+            Self::InvalidString => write!(f, "String constant is not valid UTF-8."),
+            Self::Invalid(error) => write!(f, "{}", error),
+        }
+    }
+}
+
+fn checksum(bytes: &[u8]) -> u32 {
+    // 32-bit FNV-1a
+    let mut hash: u32 = 2166136261;
+    for byte in bytes {
+        hash ^= *byte as u32;
+        hash = hash.wrapping_mul(16777619);
+    }
+    hash
+}
+
+pub fn serialize(chunk: &Chunk) -> Vec<u8> {
+    let mut body = Vec::new();
+
+    body.extend((chunk.code().len() as u32).to_le_bytes());
+    body.extend(chunk.code());
+    for line in chunk.lines() {
+        body.extend(line.to_le_bytes());
+    }
+
+    body.extend((chunk.constants.count as u32).to_le_bytes());
+    for i in 0..chunk.constants.count {
+        let value = chunk.constants[i];
+        if value.is_nil() {
+            body.push(TAG_NIL);
+        } else if value.is_bool() {
+            body.extend([TAG_BOOL, value.as_bool() as u8]);
+        } else if value.is_number() {
+            body.push(TAG_NUMBER);
+            body.extend(value.as_number().to_le_bytes());
+        } else if value.is_int() {
+            body.push(TAG_INT);
+            body.extend(value.as_int().to_le_bytes());
+        } else {
+            let s = value.as_string();
+            body.push(TAG_STRING);
+            body.extend((s.length as u32).to_le_bytes());
+            body.extend(s.as_ruststr().as_bytes());
+        }
+    }
+
+    with_header(body)
+}
+
+fn with_header(body: Vec<u8>) -> Vec<u8> {
+    let mut bytes = Vec::with_capacity(HEADER_LEN + body.len());
+    bytes.extend(MAGIC);
+    bytes.extend(VERSION.to_le_bytes());
+    bytes.extend(checksum(&body).to_le_bytes());
+    bytes.extend(body);
+    bytes
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, count: usize) -> Result<&'a [u8], LoadError> {
+        if self.bytes.len() < count {
+            return Err(LoadError::Truncated);
+        }
+        let (taken, rest) = self.bytes.split_at(count);
+        self.bytes = rest;
+        Ok(taken)
+    }
+
+    fn u8(&mut self) -> Result<u8, LoadError> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u32(&mut self) -> Result<u32, LoadError> {
+        Ok(u32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    fn u64(&mut self) -> Result<u64, LoadError> {
+        Ok(u64::from_le_bytes(self.take(8)?.try_into().unwrap()))
+    }
+}
+
+pub fn deserialize(bytes: &[u8]) -> Result<Chunk, LoadError> {
+    if bytes.len() < HEADER_LEN || &bytes[0..4] != MAGIC {
+        return Err(LoadError::BadMagic);
+    }
+    let version = u16::from_le_bytes([bytes[4], bytes[5]]);
+    if version != VERSION {
+        return Err(LoadError::UnsupportedVersion(version));
+    }
+    let body = &bytes[HEADER_LEN..];
+    if u32::from_le_bytes(bytes[6..10].try_into().unwrap()) != checksum(body) {
+        return Err(LoadError::ChecksumMismatch);
+    }
+
+    let mut reader = Reader { bytes: body };
+    let mut chunk = Chunk::new();
+
+    let count = reader.u32()? as usize;
+    let code = reader.take(count)?;
+    for &byte in code {
+        let line = reader.u32()?;
+        chunk.write(byte, line);
+    }
+
+    let constant_count = reader.u32()?;
+    for _ in 0..constant_count {
+        let value = match reader.u8()? {
+            TAG_NIL => Value::NIL,
+            TAG_BOOL => (reader.u8()? != 0).into(),
+            TAG_NUMBER => f64::from_bits(reader.u64()?).into(),
+            TAG_INT => (reader.u64()? as i64).into(),
+            TAG_STRING => {
+                let length = reader.u32()? as usize;
+                let string =
+                    str::from_utf8(reader.take(length)?).map_err(|_| LoadError::InvalidString)?;
+                let chars = allocate(length);
+                unsafe {
+                    copy_nonoverlapping(string.as_ptr(), chars, length);
+                }
+                Obj::take_string(chars, length).into()
+            }
+            tag => return Err(LoadError::InvalidConstantTag(tag)),
+        };
+        chunk.add_constant(value);
+    }
+
+    if !reader.bytes.is_empty() {
+        return Err(LoadError::TrailingBytes);
+    }
+
+    verify(&chunk).map_err(LoadError::Invalid)?;
+    Ok(chunk)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::chunk::OpCode;
+    use crate::compiler::compile;
+
+    fn compiled(source: &str) -> Chunk {
+        let mut chunk = Chunk::new();
+        assert!(compile(source, &mut chunk));
+        chunk
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let chunk = compiled("\"a\" + \"b\" == nil ?? (1 < 2.5 ? 3 : 4)");
+        let loaded = deserialize(&serialize(&chunk)).unwrap();
+
+        assert_eq!(loaded.code(), chunk.code());
+        assert_eq!(loaded.lines(), chunk.lines());
+        assert_eq!(loaded.constants.count, chunk.constants.count);
+        for i in 0..chunk.constants.count {
+            assert_eq!(loaded.constants[i], chunk.constants[i]);
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "nan_boxing")]
+    fn test_loads_any_nan_as_a_number() {
+        // Stored as is, a NaN with these bits would be an object pointer
+        let mut body = vec![3, 0, 0, 0, OpCode::Constant as u8, 0, OpCode::Return as u8];
+        body.extend([1, 0, 0, 0, 1, 0, 0, 0, 1, 0, 0, 0, 1, 0, 0, 0, TAG_NUMBER]);
+        body.extend(0xfffc_0000_dead_bee0_u64.to_le_bytes());
+        let chunk = deserialize(&with_header(body)).unwrap();
+        assert!(chunk.constants[0].as_number().is_nan());
+
+        let loaded = deserialize(&serialize(&chunk)).unwrap();
+        assert!(loaded.constants[0].as_number().is_nan());
+    }
+
+    #[test]
+    fn test_rejects_bad_header() {
+        let mut bytes = serialize(&compiled("1"));
+
+        assert_eq!(deserialize(b"LOX").err(), Some(LoadError::BadMagic));
+
+        bytes[HEADER_LEN] ^= 0xff;
+        assert_eq!(deserialize(&bytes).err(), Some(LoadError::ChecksumMismatch));
+
+        bytes[4] = 99;
+        assert_eq!(
+            deserialize(&bytes).err(),
+            Some(LoadError::UnsupportedVersion(99))
+        );
+    }
+
+    #[test]
+    fn test_rejects_invalid_code() {
+        let mut body = vec![2, 0, 0, 0, 0xfe, OpCode::Return as u8];
+        body.extend([1, 0, 0, 0, 1, 0, 0, 0, 0, 0, 0, 0]);
+        assert_eq!(
+            deserialize(&with_header(body)).err(),
+            Some(LoadError::Invalid(VerifyError::InvalidOpcode {
+                offset: 0,
+                byte: 0xfe
+            }))
+        );
+
+        let mut body = vec![3, 0, 0, 0, OpCode::Constant as u8, 0, OpCode::Return as u8];
+        body.extend([1, 0, 0, 0, 1, 0, 0, 0, 1, 0, 0, 0, 0, 0, 0, 0]);
+        assert_eq!(
+            deserialize(&with_header(body)).err(),
+            Some(LoadError::Invalid(VerifyError::InvalidConstant {
+                offset: 0,
+                index: 0
+            }))
+        );
+    }
+}
@@ -0,0 +1,40 @@
+//! Helpers shared by the unit tests
+
+use std::cell::RefCell;
+use std::io::{self, Write};
+use std::rc::Rc;
+
+use crate::chunk::Chunk;
+use crate::vm::{InterpretResult, VM};
+
+/// A writer whose contents can still be read after the VM owns it
+#[derive(Clone, Default)]
+pub(crate) struct Shared(Rc<RefCell<Vec<u8>>>);
+
+impl Shared {
+    /// Everything written so far, leaving the buffer empty
+    pub(crate) fn take(&self) -> String {
+        String::from_utf8(self.0.take()).unwrap()
+    }
+}
+
+impl Write for Shared {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.borrow_mut().write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// What the VM prints for a chunk, or None if it raises an error
+pub(crate) fn run(chunk: &Chunk) -> Option<String> {
+    let out = Shared::default();
+    let mut vm = VM::new();
+    vm.set_output(out.clone());
+    match vm.interpret_chunk(chunk) {
+        InterpretResult::Ok => Some(out.take()),
+        _ => None,
+    }
+}
@@ -0,0 +1,76 @@
+use std::str::FromStr;
+
+/// What the VM reports while it runs. Tracing is only compiled in with the
+/// `trace` feature; without it the VM accepts a config but ignores it.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct TraceConfig {
+    /// Disassemble each instruction before it executes
+    pub instructions: bool,
+    /// Print the contents of the stack before each instruction
+    pub stack: bool,
+    /// Report entering and returning from a chunk
+    pub calls: bool,
+}
+
+impl TraceConfig {
+    pub const OFF: Self = Self {
+        instructions: false,
+        stack: false,
+        calls: false,
+    };
+
+    pub const ALL: Self = Self {
+        instructions: true,
+        stack: true,
+        calls: true,
+    };
+
+    pub fn is_off(&self) -> bool {
+        *self == Self::OFF
+    }
+}
+
+impl FromStr for TraceConfig {
+    type Err = String;
+
+    /// Parse `off`, `all` or a comma-separated list of `instructions`,
+    /// `stack` and `calls`
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "off" => return Ok(Self::OFF),
+            "all" => return Ok(Self::ALL),
+            _ => {}
+        }
+
+        let mut config = Self::OFF;
+        for part in s.split(',') {
+            match part.trim() {
+                "instructions" => config.instructions = true,
+                "stack" => config.stack = true,
+                "calls" => config.calls = true,
+                other => return Err(format!("Unknown trace option '{}'.", other)),
+            }
+        }
+        Ok(config)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        assert_eq!("off".parse(), Ok(TraceConfig::OFF));
+        assert_eq!("all".parse(), Ok(TraceConfig::ALL));
+        assert_eq!(
+            "stack, calls".parse(),
+            Ok(TraceConfig {
+                instructions: false,
+                stack: true,
+                calls: true,
+            })
+        );
+        assert!("stack,everything".parse::<TraceConfig>().is_err());
+    }
+}
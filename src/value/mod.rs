@@ -0,0 +1,189 @@
+use std::cmp::Ordering;
+use std::{fmt::Display, ops::Index};
+use std::{ptr, slice};
+
+use crate::memory::{free_array, grow_array, grow_capacity};
+use crate::object::{Obj, ObjString};
+
+#[cfg(feature = "nan_boxing")]
+mod nan_boxed;
+#[cfg(not(feature = "nan_boxing"))]
+mod tagged;
+
+#[cfg(feature = "nan_boxing")]
+pub use nan_boxed::Value;
+#[cfg(not(feature = "nan_boxing"))]
+pub use tagged::Value;
+
+impl Display for Value {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.is_nil() {
+            write!(f, "nil")
+        } else if self.is_bool() {
+            write!(f, "{}", self.as_bool())
+        } else if self.is_number() {
+            write!(f, "{}", self.as_number())
+        } else if self.is_int() {
+            write!(f, "{}", self.as_int())
+        } else {
+            write!(f, "\"{}\"", unsafe { &*self.as_obj() })
+        }
+    }
+}
+
+impl PartialEq for Value {
+    fn eq(&self, other: &Self) -> bool {
+        if self.is_int() && other.is_int() {
+            self.as_int() == other.as_int()
+        } else if self.is_numeric() && other.is_numeric() {
+            self.partial_cmp(other) == Some(Ordering::Equal)
+        } else if self.is_nil() || other.is_nil() {
+            self.is_nil() && other.is_nil()
+        } else if self.is_bool() && other.is_bool() {
+            self.as_bool() == other.as_bool()
+        } else if self.is_obj() && other.is_obj() {
+            unsafe { *self.as_obj() == *other.as_obj() }
+        } else {
+            false
+        }
+    }
+}
+
+/// Numbers are ordered by their exact values, so an integer is never
+/// rounded to the nearest float to compare it with one. Anything else is
+/// only comparable with what it equals.
+impl PartialOrd for Value {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        if self.is_int() && other.is_int() {
+            Some(self.as_int().cmp(&other.as_int()))
+        } else if self.is_number() && other.is_number() {
+            self.as_number().partial_cmp(&other.as_number())
+        } else if self.is_int() && other.is_number() {
+            compare_int_to_float(self.as_int(), other.as_number())
+        } else if self.is_number() && other.is_int() {
+            compare_int_to_float(other.as_int(), self.as_number()).map(Ordering::reverse)
+        } else if self == other {
+            Some(Ordering::Equal)
+        } else {
+            None
+        }
+    }
+}
+
+/// Above 2^53 `int as f64` rounds, which would make 2^53 + 1 equal to
+/// 2^53.0, so this compares the float's whole part as an integer instead
+fn compare_int_to_float(int: i64, float: f64) -> Option<Ordering> {
+    // 2^63, just past i64::MAX. -2^63 is exactly i64::MIN.
+    const LIMIT: f64 = 9_223_372_036_854_775_808.0;
+    if float.is_nan() {
+        None
+    } else if float >= LIMIT {
+        Some(Ordering::Less)
+    } else if float < -LIMIT {
+        Some(Ordering::Greater)
+    } else {
+        let whole = float.trunc();
+        let fraction = 0.0.partial_cmp(&(float - whole))?;
+        Some(int.cmp(&(whole as i64)).then(fraction))
+    }
+}
+
+impl Value {
+    /// True for both floating point and integer values
+    pub fn is_numeric(&self) -> bool {
+        self.is_number() || self.is_int()
+    }
+
+    /// Read a numeric value as a float, promoting integers
+    pub fn as_numeric(&self) -> f64 {
+        if self.is_int() {
+            self.as_int() as f64
+        } else if self.is_number() {
+            self.as_number()
+        } else {
+            panic!("not numeric");
+        }
+    }
+
+    pub fn is_string(&self) -> bool {
+        if self.is_obj() {
+            match unsafe { &*self.as_obj() } {
+                Obj::String(_) => true,
+                // _ => false,
+            }
+        } else {
+            false
+        }
+    }
+
+    pub fn as_string(&self) -> &ObjString {
+        if self.is_obj() {
+            let Obj::String(s) = unsafe { &*self.as_obj() };
+            s
+        } else {
+            panic!("not a string");
+        }
+    }
+}
+
+pub struct ValueArray {
+    capacity: usize,
+    pub(crate) count: usize,
+    values: *mut Value,
+}
+
+impl ValueArray {
+    pub fn new() -> Self {
+        Self {
+            count: 0,
+            capacity: 0,
+            values: ptr::null_mut(),
+        }
+    }
+
+    pub fn write(&mut self, value: Value) {
+        if self.capacity < self.count + 1 {
+            let old_capacity = self.capacity;
+            self.capacity = grow_capacity(old_capacity);
+            self.values = grow_array(self.values, old_capacity, self.capacity)
+        }
+
+        unsafe {
+            ptr::write(self.values.add(self.count), value);
+        }
+        self.count += 1;
+    }
+
+    /// Forget the last value; the storage is kept for reuse
+    pub(crate) fn pop(&mut self) {
+        self.count = self.count.saturating_sub(1);
+    }
+
+    pub fn as_slice(&self) -> &[Value] {
+        if self.count == 0 {
+            return &[];
+        }
+        unsafe { slice::from_raw_parts(self.values, self.count) }
+    }
+}
+
+impl Default for ValueArray {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Index<usize> for ValueArray {
+    type Output = Value;
+    fn index(&self, index: usize) -> &Self::Output {
+        &self.as_slice()[index]
+    }
+}
+
+impl Drop for ValueArray {
+    fn drop(&mut self) {
+        free_array(self.values, self.capacity);
+        // We don't zero the fields because Rust won't let us use this
+        // after it's freed
+    }
+}
@@ -0,0 +1,169 @@
+use std::fmt::Debug;
+
+use crate::object::Obj;
+
+// A double is a quiet NaN when these bits are all set. Every NaN is
+// stored as the canonical one, which leaves the extra bit at 50 clear, so
+// every value whose bits match QNAN is one of ours rather than a number.
+const QNAN: u64 = 0x7ffc_0000_0000_0000;
+const SIGN_BIT: u64 = 0x8000_0000_0000_0000;
+
+// Below QNAN, bits 48-49 say what the low 48 bits hold: an integer, or a
+// pointer to one that needs more bits. Objects set the sign bit instead
+// and keep the pointer in the low 48 bits.
+const TAG_MASK: u64 = 0x0003_0000_0000_0000;
+const TAG_INT: u64 = 0x0001_0000_0000_0000;
+const TAG_BOXED_INT: u64 = 0x0002_0000_0000_0000;
+const PAYLOAD_MASK: u64 = 0x0000_ffff_ffff_ffff;
+
+const NIL_BITS: u64 = QNAN | 1;
+const FALSE_BITS: u64 = QNAN | 2;
+const TRUE_BITS: u64 = QNAN | 3;
+
+const INT_BITS: u32 = 48;
+
+/// A value packed into a quiet NaN: 8 bytes per stack slot and constant.
+/// Integers that fit in 48 bits are stored inline and the rest are boxed,
+/// so the full i64 range is available as with the tagged enum.
+#[derive(Clone, Copy)]
+pub struct Value(u64);
+
+impl From<bool> for Value {
+    fn from(b: bool) -> Self {
+        Self(if b { TRUE_BITS } else { FALSE_BITS })
+    }
+}
+
+impl From<f64> for Value {
+    fn from(n: f64) -> Self {
+        // Any other NaN could collide with a boxed value
+        Self(if n.is_nan() { f64::NAN } else { n }.to_bits())
+    }
+}
+
+impl From<i64> for Value {
+    fn from(n: i64) -> Self {
+        let inline = QNAN | TAG_INT | (n as u64 & PAYLOAD_MASK);
+        if sign_extend(inline) == n {
+            Self(inline)
+        } else {
+            // Never freed, like every other object until there's a collector,
+            // so each wide result an instruction produces leaks 8 bytes
+            Self(QNAN | TAG_BOXED_INT | Box::into_raw(Box::new(n)) as u64)
+        }
+    }
+}
+
+impl From<*mut Obj> for Value {
+    fn from(value: *mut Obj) -> Self {
+        Self(SIGN_BIT | QNAN | value as u64)
+    }
+}
+
+impl Debug for Value {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.is_nil() {
+            write!(f, "Nil")
+        } else if self.is_bool() {
+            f.debug_tuple("Bool").field(&self.as_bool()).finish()
+        } else if self.is_number() {
+            f.debug_tuple("Number").field(&self.as_number()).finish()
+        } else if self.is_int() {
+            f.debug_tuple("Int").field(&self.as_int()).finish()
+        } else {
+            f.debug_tuple("Obj").field(&self.as_obj()).finish()
+        }
+    }
+}
+
+impl Value {
+    pub const NIL: Self = Self(NIL_BITS);
+
+    pub fn is_nil(&self) -> bool {
+        self.0 == NIL_BITS
+    }
+
+    pub fn is_bool(&self) -> bool {
+        self.0 | 1 == TRUE_BITS
+    }
+
+    pub fn as_bool(&self) -> bool {
+        assert!(self.is_bool(), "not a bool");
+        self.0 == TRUE_BITS
+    }
+
+    pub fn is_number(&self) -> bool {
+        self.0 & QNAN != QNAN
+    }
+
+    pub fn as_number(&self) -> f64 {
+        assert!(self.is_number(), "not a number");
+        f64::from_bits(self.0)
+    }
+
+    pub fn is_int(&self) -> bool {
+        let tag = self.0 & (SIGN_BIT | QNAN | TAG_MASK);
+        tag == QNAN | TAG_INT || tag == QNAN | TAG_BOXED_INT
+    }
+
+    pub fn as_int(&self) -> i64 {
+        assert!(self.is_int(), "not an int");
+        if self.0 & TAG_MASK == TAG_INT {
+            sign_extend(self.0)
+        } else {
+            unsafe { *((self.0 & PAYLOAD_MASK) as *const i64) }
+        }
+    }
+
+    pub fn is_obj(&self) -> bool {
+        self.0 & (SIGN_BIT | QNAN) == SIGN_BIT | QNAN
+    }
+
+    pub fn as_obj(&self) -> *mut Obj {
+        assert!(self.is_obj(), "not an object");
+        (self.0 & PAYLOAD_MASK) as *mut Obj
+    }
+}
+
+/// Read the low 48 bits as a signed integer
+fn sign_extend(bits: u64) -> i64 {
+    // Shift the payload to the top and back
+    ((bits << (64 - INT_BITS)) as i64) >> (64 - INT_BITS)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_size() {
+        assert_eq!(std::mem::size_of::<Value>(), 8);
+    }
+
+    #[test]
+    fn test_round_trip() {
+        assert!(Value::NIL.is_nil());
+        assert!(!Value::NIL.is_bool());
+        assert!(Value::from(false).is_bool() && !Value::from(false).as_bool());
+        assert!(Value::from(true).as_bool());
+        assert!(Value::from(f64::NAN).is_number());
+        for bits in [0xfffc_0000_dead_bee0, QNAN | 1, QNAN | TAG_INT] {
+            let value = Value::from(f64::from_bits(bits));
+            assert!(value.is_number() && value.as_number().is_nan());
+        }
+        assert_eq!(Value::from(-2.5).as_number(), -2.5);
+
+        for n in [0, 1, -1, (1 << 47) - 1, -(1 << 47)] {
+            let value = Value::from(n);
+            assert!(value.is_int() && !value.is_number() && !value.is_obj());
+            assert_eq!(value.as_int(), n);
+            assert_eq!(value.0 & TAG_MASK, TAG_INT);
+        }
+        for n in [1 << 47, -(1 << 47) - 1, i64::MAX, i64::MIN] {
+            let value = Value::from(n);
+            assert!(value.is_int() && !value.is_number() && !value.is_obj());
+            assert!(!value.is_nil() && !value.is_bool());
+            assert_eq!(value.as_int(), n);
+        }
+    }
+}
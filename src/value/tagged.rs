@@ -0,0 +1,92 @@
+use crate::object::Obj;
+
+/// A value as a tagged enum: 16 bytes, but every field is directly
+/// readable, which is easier to inspect in a debugger than NaN boxing
+#[derive(Debug, Clone, Copy)]
+pub enum Value {
+    Nil,
+    Bool(bool),
+    Number(f64),
+    Int(i64),
+    Obj(*mut Obj),
+}
+
+impl From<bool> for Value {
+    fn from(b: bool) -> Self {
+        Self::Bool(b)
+    }
+}
+
+impl From<f64> for Value {
+    fn from(n: f64) -> Self {
+        Self::Number(n)
+    }
+}
+
+impl From<i64> for Value {
+    fn from(n: i64) -> Self {
+        Self::Int(n)
+    }
+}
+
+impl From<*mut Obj> for Value {
+    fn from(value: *mut Obj) -> Self {
+        Self::Obj(value)
+    }
+}
+
+impl Value {
+    pub const NIL: Self = Self::Nil;
+
+    pub fn is_nil(&self) -> bool {
+        matches!(self, Self::Nil)
+    }
+
+    pub fn is_bool(&self) -> bool {
+        matches!(self, Self::Bool(_))
+    }
+
+    pub fn as_bool(&self) -> bool {
+        if let Self::Bool(b) = self {
+            *b
+        } else {
+            panic!("not a bool");
+        }
+    }
+
+    pub fn is_number(&self) -> bool {
+        matches!(self, Self::Number(_))
+    }
+
+    pub fn as_number(&self) -> f64 {
+        if let Self::Number(n) = self {
+            *n
+        } else {
+            panic!("not a number");
+        }
+    }
+
+    pub fn is_int(&self) -> bool {
+        matches!(self, Self::Int(_))
+    }
+
+    pub fn as_int(&self) -> i64 {
+        if let Self::Int(n) = self {
+            *n
+        } else {
+            panic!("not an int");
+        }
+    }
+
+    pub fn is_obj(&self) -> bool {
+        matches!(self, Self::Obj(_))
+    }
+
+    pub fn as_obj(&self) -> *mut Obj {
+        if let Self::Obj(o) = self {
+            *o
+        } else {
+            panic!("not an object");
+        }
+    }
+}
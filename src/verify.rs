@@ -0,0 +1,275 @@
+use std::fmt::Display;
+
+use crate::chunk::{Chunk, DecodeError, Instruction, OpCode};
+use crate::vm::MAX_STACK;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VerifyError {
+    InvalidOpcode { offset: usize, byte: u8 },
+    TruncatedOperand { offset: usize },
+    InvalidConstant { offset: usize, index: u8 },
+    InvalidJump { offset: usize, target: usize },
+    StackUnderflow { offset: usize },
+    StackOverflow { offset: usize },
+    InconsistentStack { offset: usize },
+    FallsOffEnd { offset: usize },
+}
+
+impl Display for VerifyError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::InvalidOpcode { offset, byte } => {
+                write!(f, "Invalid opcode {} at offset {}.", byte, offset)
+            }
+            Self::TruncatedOperand { offset } => {
+                write!(f, "Missing operand for instruction at offset {}.", offset)
+            }
+            Self::InvalidConstant { offset, index } => {
+                write!(f, "Invalid constant {} at offset {}.", index, offset)
+            }
+            Self::InvalidJump { offset, target } => {
+                write!(f, "Invalid jump target {} at offset {}.", target, offset)
+            }
+            Self::StackUnderflow { offset } => write!(f, "Stack underflow at offset {}.", offset),
+            Self::StackOverflow { offset } => write!(f, "Stack overflow at offset {}.", offset),
+            Self::InconsistentStack { offset } => {
+                write!(f, "Inconsistent stack depth at offset {}.", offset)
+            }
+            Self::FallsOffEnd { offset } => {
+                write!(
+                    f,
+                    "Execution runs past the end of the code at offset {}.",
+                    offset
+                )
+            }
+        }
+    }
+}
+
+/// The number of values an instruction pops, and the number it pushes
+fn stack_effect(op: OpCode) -> (usize, usize) {
+    match op {
+        OpCode::Constant | OpCode::Nil | OpCode::True | OpCode::False => (0, 1),
+        OpCode::Not | OpCode::Negate | OpCode::BitNot | OpCode::ConstantAdd => (1, 1),
+        // Conditional jumps only peek at the top of the stack. The fall
+        // through of JumpIfFalsePop pops it as well, which verify handles.
+        OpCode::JumpIfFalse | OpCode::JumpIfNotNil | OpCode::JumpIfFalsePop => (1, 1),
+        OpCode::Jump => (0, 0),
+        OpCode::Pop | OpCode::Return => (1, 0),
+        _ => (2, 1),
+    }
+}
+
+fn jump_target(code: &[u8], offset: usize) -> usize {
+    offset + 3 + u16::from_be_bytes([code[offset + 1], code[offset + 2]]) as usize
+}
+
+/// Check that a chunk is safe to hand to the VM: every instruction decodes,
+/// every constant and jump lands somewhere valid, and every path through the
+/// code keeps the stack within bounds and ends in a return.
+pub fn verify(chunk: &Chunk) -> Result<(), VerifyError> {
+    let code = chunk.code();
+
+    // First pass: decode linearly and record where instructions start
+    let mut starts = vec![false; code.len()];
+    let mut offset = 0;
+    while offset < code.len() {
+        let instruction = Instruction::decode(code, offset).map_err(|error| match error {
+            DecodeError::InvalidOpcode { offset, byte } => {
+                VerifyError::InvalidOpcode { offset, byte }
+            }
+            DecodeError::Truncated { offset } => VerifyError::TruncatedOperand { offset },
+        })?;
+        if let Instruction::Constant(index) | Instruction::ConstantAdd(index) = instruction {
+            if index as usize >= chunk.constants.count {
+                return Err(VerifyError::InvalidConstant { offset, index });
+            }
+        }
+        starts[offset] = true;
+        offset += instruction.size();
+    }
+
+    // Second pass: follow control flow, tracking the stack depth
+    let mut depths: Vec<Option<usize>> = vec![None; code.len()];
+    let mut worklist = vec![(0, 0)];
+    while let Some((offset, depth)) = worklist.pop() {
+        if offset >= code.len() {
+            return Err(VerifyError::FallsOffEnd { offset });
+        }
+        match depths[offset] {
+            Some(seen) if seen == depth => continue,
+            Some(_) => return Err(VerifyError::InconsistentStack { offset }),
+            None => depths[offset] = Some(depth),
+        }
+
+        let op = OpCode::try_from(code[offset]).unwrap();
+        let (pops, pushes) = stack_effect(op);
+        if depth < pops {
+            return Err(VerifyError::StackUnderflow { offset });
+        }
+        // ConstantAdd pushes its constant before adding, so it briefly
+        // needs a slot more than its net effect
+        if op == OpCode::ConstantAdd && depth >= MAX_STACK {
+            return Err(VerifyError::StackOverflow { offset });
+        }
+        let depth = depth - pops + pushes;
+        if depth > MAX_STACK {
+            return Err(VerifyError::StackOverflow { offset });
+        }
+
+        if op.is_jump() {
+            let target = jump_target(code, offset);
+            if target >= code.len() || !starts[target] {
+                return Err(VerifyError::InvalidJump { offset, target });
+            }
+            worklist.push((target, depth));
+        }
+        if !matches!(op, OpCode::Jump | OpCode::Return) {
+            let depth = if op == OpCode::JumpIfFalsePop {
+                depth - 1
+            } else {
+                depth
+            };
+            worklist.push((offset + 1 + op.operand_len(), depth));
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::compiler::compile;
+
+    fn chunk_of(code: &[u8]) -> Chunk {
+        let mut chunk = Chunk::new();
+        chunk.add_constant(1.into());
+        for byte in code {
+            chunk.write(*byte, 1);
+        }
+        chunk
+    }
+
+    #[test]
+    fn test_compiled_chunks_verify() {
+        for source in [
+            "1",
+            "-(1 + 2) * 3 ** 4 % 5",
+            "nil ?? (true ? \"a\" : false ? 1 : 2)",
+        ] {
+            let mut chunk = Chunk::new();
+            assert!(compile(source, &mut chunk));
+            assert_eq!(verify(&chunk), Ok(()));
+        }
+    }
+
+    #[test]
+    fn test_rejects_malformed_code() {
+        const CONSTANT: u8 = OpCode::Constant as u8;
+        const RETURN: u8 = OpCode::Return as u8;
+        const JUMP: u8 = OpCode::Jump as u8;
+
+        assert_eq!(
+            verify(&chunk_of(&[0xfe])),
+            Err(VerifyError::InvalidOpcode {
+                offset: 0,
+                byte: 0xfe
+            })
+        );
+        assert_eq!(
+            verify(&chunk_of(&[CONSTANT])),
+            Err(VerifyError::TruncatedOperand { offset: 0 })
+        );
+        assert_eq!(
+            verify(&chunk_of(&[CONSTANT, 1, RETURN])),
+            Err(VerifyError::InvalidConstant {
+                offset: 0,
+                index: 1
+            })
+        );
+        assert_eq!(
+            verify(&chunk_of(&[CONSTANT, 0, JUMP, 0, 1, CONSTANT, 0, RETURN])),
+            Err(VerifyError::InvalidJump {
+                offset: 2,
+                target: 6
+            })
+        );
+    }
+
+    #[test]
+    fn test_jump_if_false_pop_depths() {
+        const TRUE: u8 = OpCode::True as u8;
+        const NIL: u8 = OpCode::Nil as u8;
+        const RETURN: u8 = OpCode::Return as u8;
+        const JUMP_IF_FALSE_POP: u8 = OpCode::JumpIfFalsePop as u8;
+
+        // The jump keeps the condition and the fall through pops it
+        assert_eq!(
+            verify(&chunk_of(&[TRUE, JUMP_IF_FALSE_POP, 0, 1, NIL, RETURN])),
+            Ok(())
+        );
+        assert_eq!(
+            verify(&chunk_of(&[TRUE, JUMP_IF_FALSE_POP, 0, 0, RETURN])),
+            Err(VerifyError::StackUnderflow { offset: 4 })
+        );
+    }
+
+    #[test]
+    fn test_rejects_unbalanced_stack() {
+        const CONSTANT: u8 = OpCode::Constant as u8;
+        const RETURN: u8 = OpCode::Return as u8;
+        const JUMP_IF_FALSE: u8 = OpCode::JumpIfFalse as u8;
+
+        assert_eq!(
+            verify(&chunk_of(&[RETURN])),
+            Err(VerifyError::StackUnderflow { offset: 0 })
+        );
+        assert_eq!(
+            verify(&chunk_of(&[CONSTANT, 0])),
+            Err(VerifyError::FallsOffEnd { offset: 2 })
+        );
+        assert_eq!(
+            verify(&chunk_of(&[
+                CONSTANT,
+                0,
+                JUMP_IF_FALSE,
+                0,
+                2,
+                CONSTANT,
+                0,
+                RETURN
+            ])),
+            Err(VerifyError::InconsistentStack { offset: 7 })
+        );
+
+        let mut code = [CONSTANT, 0].repeat(MAX_STACK + 1);
+        code.push(RETURN);
+        assert_eq!(
+            verify(&chunk_of(&code)),
+            Err(VerifyError::StackOverflow {
+                offset: 2 * MAX_STACK
+            })
+        );
+    }
+
+    #[test]
+    fn test_constant_add_needs_a_free_slot() {
+        const CONSTANT: u8 = OpCode::Constant as u8;
+        const CONSTANT_ADD: u8 = OpCode::ConstantAdd as u8;
+        const RETURN: u8 = OpCode::Return as u8;
+
+        let mut code = [CONSTANT, 0].repeat(MAX_STACK - 1);
+        code.extend([CONSTANT_ADD, 0, RETURN]);
+        assert_eq!(verify(&chunk_of(&code)), Ok(()));
+
+        let mut code = [CONSTANT, 0].repeat(MAX_STACK);
+        code.extend([CONSTANT_ADD, 0, RETURN]);
+        assert_eq!(
+            verify(&chunk_of(&code)),
+            Err(VerifyError::StackOverflow {
+                offset: 2 * MAX_STACK
+            })
+        );
+    }
+}
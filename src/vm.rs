@@ -1,38 +1,27 @@
 use crate::chunk::{Chunk, OpCode};
 use crate::compiler::compile;
-#[cfg(debug_assertions)]
-use crate::debug::disassemble_instruction;
-use crate::memory::allocate;
+#[cfg(feature = "trace")]
+use crate::debug::write_instruction;
 use crate::object::Obj;
+use crate::optimizer::OptLevel;
+use crate::trace::TraceConfig;
 use crate::value::Value;
+use crate::verify::verify;
 
-use std::ptr::{self, copy_nonoverlapping};
+use std::io::{self, Write};
+use std::ptr;
 
-const MAX_STACK: usize = 256;
+pub(crate) const MAX_STACK: usize = 256;
 
+#[derive(Debug, PartialEq, Eq)]
 pub enum InterpretResult {
     Ok,
     CompileError,
     RuntimeError,
 }
 
-macro_rules! binary_op {
-    ($stack:ident, $op:tt) => {
-        {
-            if $stack.peek(0).is_number() && $stack.peek(1).is_number() {
-                let b = $stack.pop().as_number();
-                let a = $stack.pop().as_number();
-                $stack.push((a $op b).into());
-            } else {
-                $stack.runtime_error("Operands must be numbers.");
-                return InterpretResult::RuntimeError;
-            }
-        }
-    }
-}
-
-fn is_falsey(value: Value) -> bool {
-    value == Value::Nil || value == Value::Bool(false)
+pub(crate) fn is_falsey(value: Value) -> bool {
+    value.is_nil() || (value.is_bool() && !value.as_bool())
 }
 
 pub struct VM {
@@ -40,6 +29,16 @@ pub struct VM {
     ip: *const u8,
     stack: [Value; MAX_STACK],
     stack_top: *mut Value,
+    trace: TraceConfig,
+    trace_out: Box<dyn Write>,
+    out: Box<dyn Write>,
+    opt_level: OptLevel,
+}
+
+impl Default for VM {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl VM {
@@ -49,11 +48,42 @@ impl VM {
             ip: ptr::null(),
             stack: [0.0.into(); MAX_STACK],
             stack_top: ptr::null_mut(),
+            trace: TraceConfig::OFF,
+            trace_out: Box::new(io::stderr()),
+            out: Box::new(io::stdout()),
+            opt_level: OptLevel::default(),
         };
         value.reset_stack();
         value
     }
 
+    pub fn trace(&self) -> TraceConfig {
+        self.trace
+    }
+
+    pub fn set_trace(&mut self, config: TraceConfig) {
+        self.trace = config;
+    }
+
+    pub fn opt_level(&self) -> OptLevel {
+        self.opt_level
+    }
+
+    /// How much to optimize the code compiled by [`VM::interpret`]
+    pub fn set_opt_level(&mut self, level: OptLevel) {
+        self.opt_level = level;
+    }
+
+    /// Send trace output somewhere other than stderr
+    pub fn set_trace_writer(&mut self, out: impl Write + 'static) {
+        self.trace_out = Box::new(out);
+    }
+
+    /// Send the values scripts print somewhere other than stdout
+    pub fn set_output(&mut self, out: impl Write + 'static) {
+        self.out = Box::new(out);
+    }
+
     fn runtime_error(&mut self, message: &str) {
         eprintln!("{}", message);
 
@@ -81,6 +111,7 @@ impl VM {
         }
     }
 
+    #[cfg(test)]
     fn peek(&self, distance: usize) -> &Value {
         unsafe { self.stack_top.sub(1 + distance).as_ref().unwrap() }
     }
@@ -92,115 +123,401 @@ impl VM {
             return InterpretResult::CompileError;
         }
 
-        self.chunk = &chunk as *const Chunk;
+        match self.opt_level.apply(chunk) {
+            Ok(chunk) => self.run_chunk(&chunk),
+            Err(error) => {
+                eprintln!("Could not optimize: {}", error);
+                InterpretResult::CompileError
+            }
+        }
+    }
+
+    /// Run a chunk that didn't come from the compiler, after checking that
+    /// it's safe to execute
+    pub fn interpret_chunk(&mut self, chunk: &Chunk) -> InterpretResult {
+        if let Err(error) = verify(chunk) {
+            eprintln!("Invalid chunk: {}", error);
+            return InterpretResult::CompileError;
+        }
+
+        self.run_chunk(chunk)
+    }
+
+    /// Run a chunk without verifying it first, for callers that run the
+    /// same chunk many times and verified it once up front
+    ///
+    /// # Safety
+    ///
+    /// `chunk` must have passed [`verify`].
+    pub unsafe fn interpret_chunk_unchecked(&mut self, chunk: &Chunk) -> InterpretResult {
+        self.run_chunk(chunk)
+    }
+
+    fn run_chunk(&mut self, chunk: &Chunk) -> InterpretResult {
+        self.chunk = chunk as *const Chunk;
         self.ip = chunk.code as *const u8;
+        // The stack array moves with the VM, so the top pointer has to be
+        // re-derived from wherever it lives now
+        self.reset_stack();
+
+        #[cfg(feature = "trace")]
+        if self.trace.calls {
+            let _ = writeln!(self.trace_out, "-> script");
+        }
 
         let result = self.run();
 
+        #[cfg(feature = "trace")]
+        if self.trace.calls {
+            let _ = writeln!(self.trace_out, "<- script ({:?})", result);
+        }
+
         self.chunk = ptr::null();
         self.ip = ptr::null();
 
         result
     }
 
-    #[inline]
-    unsafe fn read_byte(&mut self) -> u8 {
-        let value = *self.ip;
-        self.ip = self.ip.offset(1);
-        value
-    }
-
-    #[inline]
-    unsafe fn read_constant(&mut self) -> Value {
-        (*(self.chunk)).constants[self.read_byte().into()]
-    }
-
     fn concatenate(&mut self) {
         let b = self.pop();
         let a = self.pop();
 
-        let b_str = b.as_string();
-        let a_str = a.as_string();
+        self.push(Obj::concat(a.as_string(), b.as_string()).into())
+    }
 
-        let length = a_str.length + b_str.length;
-        let chars = allocate(length);
-        unsafe {
-            copy_nonoverlapping(a_str.chars, chars, a_str.length);
-            copy_nonoverlapping(b_str.chars, chars.add(a_str.length), b_str.length);
+    #[cfg(feature = "trace")]
+    fn trace_instruction(&mut self) {
+        let mut out = String::new();
+        if self.trace.stack {
+            out.push_str("          ");
+            let mut slot = self.stack.as_ptr();
+            while slot != self.stack_top {
+                out.push_str(&format!("[ {} ]", unsafe { *slot }));
+                slot = unsafe { slot.add(1) };
+            }
+            out.push('\n');
         }
-
-        self.push(Obj::take_string(chars, length).into())
+        if self.trace.instructions {
+            let chunk = unsafe { &*self.chunk };
+            let offset = unsafe { self.ip.offset_from(chunk.code) } as usize;
+            write_instruction(&mut out, chunk, offset).unwrap();
+        }
+        // A failing trace writer shouldn't stop the program
+        let _ = self.trace_out.write_all(out.as_bytes());
     }
 
     fn run(&mut self) -> InterpretResult {
+        // The instruction pointer, stack top and constants live in locals so
+        // they can stay in registers. They are written back to the VM only
+        // where something outside this loop needs to see them.
+        let constants = unsafe { (*self.chunk).constants.as_slice() };
+        let mut ip = self.ip;
+        let mut sp = self.stack_top;
+        #[cfg(feature = "trace")]
+        let tracing = !self.trace.is_off();
+
+        macro_rules! read_byte {
+            () => {{
+                let byte = unsafe { *ip };
+                ip = unsafe { ip.add(1) };
+                byte
+            }};
+        }
+
+        macro_rules! read_short {
+            () => {{
+                let high = read_byte!();
+                let low = read_byte!();
+                u16::from_be_bytes([high, low])
+            }};
+        }
+
+        macro_rules! push {
+            ($value:expr) => {{
+                let value: Value = $value;
+                unsafe {
+                    sp.write(value);
+                    sp = sp.add(1);
+                }
+            }};
+        }
+
+        macro_rules! pop {
+            () => {
+                unsafe {
+                    sp = sp.sub(1);
+                    *sp
+                }
+            };
+        }
+
+        macro_rules! discard {
+            ($count:expr) => {
+                sp = unsafe { sp.sub($count) }
+            };
+        }
+
+        macro_rules! peek {
+            ($distance:expr) => {
+                unsafe { *sp.sub(1 + $distance) }
+            };
+        }
+
+        macro_rules! sync {
+            () => {
+                self.ip = ip;
+                self.stack_top = sp;
+            };
+        }
+
+        macro_rules! runtime_error {
+            ($message:expr) => {{
+                sync!();
+                self.runtime_error($message);
+                return InterpretResult::RuntimeError;
+            }};
+        }
+
+        macro_rules! binary_op {
+            ($op:tt) => {{
+                let (a, b) = (peek!(1), peek!(0));
+                if a.is_int() && b.is_int() {
+                    discard!(2);
+                    push!((a.as_int() $op b.as_int()).into());
+                } else if a.is_numeric() && b.is_numeric() {
+                    discard!(2);
+                    push!((a $op b).into());
+                } else {
+                    runtime_error!("Operands must be numbers.");
+                }
+            }};
+            // With nan_boxing, an integer result wider than 48 bits is boxed
+            // on the heap and never freed (see Value::from). The same goes
+            // for \, **, shifts and negation below.
+            ($checked:ident, $op:tt) => {{
+                let (a, b) = (peek!(1), peek!(0));
+                if a.is_int() && b.is_int() {
+                    match a.as_int().$checked(b.as_int()).map(Value::from) {
+                        Some(value) => {
+                            discard!(2);
+                            push!(value);
+                        }
+                        None => runtime_error!("Integer overflow."),
+                    }
+                } else if a.is_numeric() && b.is_numeric() {
+                    discard!(2);
+                    push!((a.as_numeric() $op b.as_numeric()).into());
+                } else {
+                    runtime_error!("Operands must be numbers.");
+                }
+            }};
+        }
+
+        macro_rules! bitwise_op {
+            ($op:tt) => {{
+                let (a, b) = (peek!(1), peek!(0));
+                if a.is_int() && b.is_int() {
+                    discard!(2);
+                    push!((a.as_int() $op b.as_int()).into());
+                } else {
+                    runtime_error!("Operands must be integers.");
+                }
+            }};
+        }
+
+        macro_rules! shift_op {
+            ($checked:ident) => {{
+                let (a, b) = (peek!(1), peek!(0));
+                if a.is_int() && b.is_int() {
+                    let shifted = u32::try_from(b.as_int())
+                        .ok()
+                        .and_then(|b| a.as_int().$checked(b));
+                    match shifted {
+                        Some(n) => {
+                            discard!(2);
+                            push!(Value::from(n));
+                        }
+                        None => runtime_error!("Shift amount out of range."),
+                    }
+                } else {
+                    runtime_error!("Operands must be integers.");
+                }
+            }};
+        }
+
+        macro_rules! add {
+            () => {{
+                if peek!(0).is_string() && peek!(1).is_string() {
+                    sync!();
+                    self.concatenate();
+                    sp = self.stack_top;
+                } else if peek!(0).is_numeric() && peek!(1).is_numeric() {
+                    binary_op!(checked_add, +)
+                } else {
+                    runtime_error!("Operands must both be numbers or strings.");
+                }
+            }};
+        }
+
         loop {
-            #[cfg(debug_assertions)]
-            unsafe {
-                print!("          ");
-                let mut slot = self.stack.as_mut_ptr();
-                while slot != self.stack_top {
-                    print!("[ {} ]", *slot);
-                    slot = slot.add(1);
-                }
-                println!("");
-                disassemble_instruction(
-                    &*self.chunk,
-                    self.ip.offset_from((*self.chunk).code) as usize,
-                );
+            #[cfg(feature = "trace")]
+            if tracing {
+                sync!();
+                self.trace_instruction();
             }
 
-            let instruction = unsafe { OpCode::from_byte(self.read_byte()) };
-
-            match instruction {
+            // Verified chunks only contain valid opcodes, and a match on a
+            // fieldless enum compiles to a jump table
+            let instruction = read_byte!();
+            match unsafe { OpCode::from_byte(instruction) } {
                 OpCode::Constant => {
-                    let constant = unsafe { self.read_constant() };
-                    self.push(constant);
+                    let constant = constants[read_byte!() as usize];
+                    push!(constant);
                 }
-                OpCode::Nil => self.push(Value::Nil),
-                OpCode::True => self.push(Value::Bool(true)),
-                OpCode::False => self.push(Value::Bool(false)),
+                OpCode::Nil => push!(Value::NIL),
+                OpCode::True => push!(true.into()),
+                OpCode::False => push!(false.into()),
                 OpCode::Equal => {
-                    let b = self.pop();
-                    let a = self.pop();
-                    self.push((a == b).into());
-                }
-                OpCode::Greater => binary_op!(self, >),
-                OpCode::Less => binary_op!(self, <),
-                OpCode::Add => {
-                    if self.peek(0).is_string() && self.peek(1).is_string() {
-                        self.concatenate();
+                    let b = pop!();
+                    let a = pop!();
+                    push!((a == b).into());
+                }
+                OpCode::Greater => binary_op!(>),
+                OpCode::Less => binary_op!(<),
+                OpCode::ConstantAdd => {
+                    let constant = constants[read_byte!() as usize];
+                    push!(constant);
+                    add!();
+                }
+                OpCode::NotEqual => {
+                    let b = pop!();
+                    let a = pop!();
+                    push!((a != b).into());
+                }
+                OpCode::GreaterEqual => binary_op!(>=),
+                OpCode::LessEqual => binary_op!(<=),
+                OpCode::Add => add!(),
+                OpCode::Subtract => binary_op!(checked_sub, -),
+                OpCode::Multiply => binary_op!(checked_mul, *),
+                OpCode::Divide => {
+                    // Division always produces a float, even for two integers
+                    if peek!(0).is_numeric() && peek!(1).is_numeric() {
+                        let b = pop!().as_numeric();
+                        let a = pop!().as_numeric();
+                        push!((a / b).into());
                     } else {
-                        if self.peek(0).is_number() && self.peek(1).is_number() {
-                            let b = self.pop().as_number();
-                            let a = self.pop().as_number();
-                            self.push((a + b).into());
-                        } else {
-                            self.runtime_error("Operands must both be numbers or strings.");
-                            return InterpretResult::RuntimeError;
+                        runtime_error!("Operands must be numbers.");
+                    }
+                }
+                OpCode::IntDivide => {
+                    let (a, b) = (peek!(1), peek!(0));
+                    if !(a.is_int() && b.is_int()) {
+                        runtime_error!("Operands must be integers.");
+                    }
+                    if b.as_int() == 0 {
+                        runtime_error!("Division by zero.");
+                    }
+                    // Truncates, so it pairs with % as a == (a \ b) * b + a % b
+                    match a.as_int().checked_div(b.as_int()).map(Value::from) {
+                        Some(value) => {
+                            discard!(2);
+                            push!(value);
                         }
+                        None => runtime_error!("Integer overflow."),
                     }
                 }
-                OpCode::Subtract => binary_op!(self, -),
-                OpCode::Multiply => binary_op!(self, *),
-                OpCode::Divide => binary_op!(self, /),
+                OpCode::Modulo => {
+                    if peek!(0).is_int() && peek!(1).is_int() && peek!(0).as_int() == 0 {
+                        runtime_error!("Division by zero.");
+                    }
+                    binary_op!(checked_rem, %)
+                }
+                OpCode::Power => {
+                    let (a, b) = (peek!(1), peek!(0));
+                    if a.is_int() && b.is_int() && b.as_int() >= 0 {
+                        let power = u32::try_from(b.as_int())
+                            .ok()
+                            .and_then(|b| a.as_int().checked_pow(b))
+                            .map(Value::from);
+                        match power {
+                            Some(value) => {
+                                discard!(2);
+                                push!(value);
+                            }
+                            None => runtime_error!("Integer overflow."),
+                        }
+                    } else if a.is_numeric() && b.is_numeric() {
+                        discard!(2);
+                        push!(a.as_numeric().powf(b.as_numeric()).into());
+                    } else {
+                        runtime_error!("Operands must be numbers.");
+                    }
+                }
+                OpCode::BitAnd => bitwise_op!(&),
+                OpCode::BitOr => bitwise_op!(|),
+                OpCode::BitXor => bitwise_op!(^),
+                OpCode::ShiftLeft => shift_op!(checked_shl),
+                OpCode::ShiftRight => shift_op!(checked_shr),
                 OpCode::Not => {
-                    let value = self.pop();
-                    self.push(is_falsey(value).into())
+                    let value = pop!();
+                    push!(is_falsey(value).into())
                 }
                 OpCode::Negate => {
-                    if self.peek(0).is_number() {
-                        let n = self.pop().as_number();
-                        self.push((-n).into());
+                    let value = peek!(0);
+                    if value.is_int() {
+                        match value.as_int().checked_neg().map(Value::from) {
+                            Some(value) => {
+                                discard!(1);
+                                push!(value);
+                            }
+                            None => runtime_error!("Integer overflow."),
+                        }
+                    } else if value.is_number() {
+                        discard!(1);
+                        push!((-value.as_number()).into());
+                    } else {
+                        runtime_error!("Operand must be a number");
+                    }
+                }
+                OpCode::BitNot => {
+                    if peek!(0).is_int() {
+                        let n = pop!().as_int();
+                        push!((!n).into());
                     } else {
-                        self.runtime_error("Operand must be a number");
-                        return InterpretResult::RuntimeError;
+                        runtime_error!("Operand must be an integer.");
+                    }
+                }
+                OpCode::Pop => discard!(1),
+                OpCode::Jump => {
+                    let offset = read_short!();
+                    ip = unsafe { ip.add(offset.into()) };
+                }
+                OpCode::JumpIfFalse => {
+                    let offset = read_short!();
+                    if is_falsey(peek!(0)) {
+                        ip = unsafe { ip.add(offset.into()) };
+                    }
+                }
+                OpCode::JumpIfNotNil => {
+                    let offset = read_short!();
+                    if !peek!(0).is_nil() {
+                        ip = unsafe { ip.add(offset.into()) };
+                    }
+                }
+                OpCode::JumpIfFalsePop => {
+                    let offset = read_short!();
+                    if is_falsey(peek!(0)) {
+                        ip = unsafe { ip.add(offset.into()) };
+                    } else {
+                        discard!(1);
                     }
                 }
                 OpCode::Return => {
-                    println!("{}", self.pop());
+                    let value = pop!();
+                    sync!();
+                    let _ = writeln!(self.out, "{}", value);
                     return InterpretResult::Ok;
                 }
-                _ => unimplemented!(),
             }
         }
     }
@@ -209,19 +526,167 @@ impl VM {
 #[cfg(test)]
 mod test {
     use super::*;
+    use crate::testing::Shared;
 
     #[test]
     fn test_stack() {
         let mut vm = VM::new();
+        vm.reset_stack();
+
+        vm.push(Value::NIL);
+        assert_eq!(vm.peek(0), &Value::NIL);
+        assert_eq!(vm.pop(), Value::NIL);
+
+        vm.push(true.into());
+        vm.push(25.0.into());
+        assert_eq!(vm.peek(1), &true.into());
+        assert_eq!(vm.pop(), 25.0.into());
+        assert_eq!(vm.pop(), true.into());
+    }
+
+    #[cfg(feature = "trace")]
+    #[test]
+    fn test_trace() {
+        let out = Shared::default();
+        let mut vm = VM::new();
+        vm.set_trace_writer(out.clone());
+        vm.set_trace(TraceConfig::ALL);
+        vm.set_opt_level(OptLevel::O0);
+        assert_eq!(vm.interpret("(nil ?? 1) + 2"), InterpretResult::Ok);
+
+        assert_eq!(
+            out.take(),
+            concat!(
+                "-> script\n",
+                "          \n",
+                "0000    1 OP_NIL\n",
+                "          [ nil ]\n",
+                "0001    | OP_JUMP_IF_NOT_NIL    1 -> 7\n",
+                "          [ nil ]\n",
+                "0004    | OP_POP\n",
+                "          \n",
+                "0005    | OP_CONSTANT         0 '1'\n",
+                "          [ 1 ]\n",
+                "0007    | OP_CONSTANT         1 '2'\n",
+                "          [ 1 ][ 2 ]\n",
+                "0009    | OP_ADD\n",
+                "          [ 3 ]\n",
+                "0010    | OP_RETURN\n",
+                "<- script (Ok)\n",
+            )
+        );
+    }
+
+    #[test]
+    fn test_comparisons() {
+        let out = Shared::default();
+        let mut vm = VM::new();
+        vm.set_output(out.clone());
+
+        for source in [
+            "1 != 2",
+            "2 >= 2",
+            "2 <= 1.5",
+            "nil != false",
+            // Every comparison with NaN is false, so >= isn't !(a < b)
+            "0 / 0 >= 1",
+            "0 / 0 != 0 / 0",
+        ] {
+            assert_eq!(vm.interpret(source), InterpretResult::Ok);
+        }
+        assert_eq!(out.take(), "true\ntrue\nfalse\ntrue\nfalse\ntrue\n");
+        assert_eq!(vm.interpret("1 <= \"x\""), InterpretResult::RuntimeError);
+    }
+
+    #[test]
+    fn test_power() {
+        let out = Shared::default();
+        let mut vm = VM::new();
+        vm.set_output(out.clone());
+
+        // The same with literals the compiler folds and with values it can't
+        for source in [
+            "2 ** 3 ** 2",
+            "-2 ** 2",
+            "(nil ?? 2) ** (nil ?? 3) ** (nil ?? 2)",
+            "-(nil ?? 2) ** (nil ?? 2)",
+        ] {
+            assert_eq!(vm.interpret(source), InterpretResult::Ok, "{}", source);
+        }
+        assert_eq!(out.take(), "512\n-4\n512\n-4\n");
+    }
 
-        vm.push(Value::Nil);
-        assert_eq!(vm.peek(0), &Value::Nil);
-        assert_eq!(vm.pop(), Value::Nil);
+    #[test]
+    fn test_integer_arithmetic() {
+        let out = Shared::default();
+        let mut vm = VM::new();
+        vm.set_output(out.clone());
+
+        for (source, expected) in [
+            ("(7 % 3 << 4 | 1) ^ ~0", "-18"),
+            ("2 * 3.5 - 1", "6"),
+            ("7 \\ 2", "3"),
+            ("-7 \\ 2", "-3"),
+            ("-7 % 2", "-1"),
+        ] {
+            assert_eq!(vm.interpret(source), InterpretResult::Ok, "{}", source);
+            assert_eq!(out.take(), format!("{}\n", expected), "{}", source);
+        }
+        assert_eq!(
+            vm.interpret("(-9223372036854775807 - 1) \\ -1"),
+            InterpretResult::RuntimeError
+        );
+        assert_eq!(vm.interpret("7.0 \\ 2"), InterpretResult::RuntimeError);
+        assert_eq!(vm.interpret("7 \\ 0"), InterpretResult::RuntimeError);
+        assert_eq!(
+            vm.interpret("3037000500 * 3037000500"),
+            InterpretResult::RuntimeError
+        );
+        assert_eq!(vm.interpret("1 % 0"), InterpretResult::RuntimeError);
+        assert_eq!(vm.interpret("2 ** 63"), InterpretResult::RuntimeError);
+        assert_eq!(vm.interpret("1 << 64"), InterpretResult::RuntimeError);
+        assert_eq!(vm.interpret("1.5 & 1"), InterpretResult::RuntimeError);
+        assert_eq!(
+            vm.interpret("9223372036854775808"),
+            InterpretResult::CompileError
+        );
+    }
+
+    #[cfg(feature = "nan_boxing")]
+    #[test]
+    fn test_boxed_integer_range() {
+        let out = Shared::default();
+        let mut vm = VM::new();
+        vm.set_output(out.clone());
+
+        for (source, expected) in [
+            ("-140737488355327 - 1", "-140737488355328"),
+            ("(nil ?? -140737488355328) - 1", "-140737488355329"),
+            ("(nil ?? 140737488355327) + 1", "140737488355328"),
+            ("(nil ?? 1) << 47", "140737488355328"),
+            ("9223372036854775807", "9223372036854775807"),
+        ] {
+            assert_eq!(vm.interpret(source), InterpretResult::Ok);
+            assert_eq!(out.take(), format!("{}\n", expected));
+        }
+        assert_eq!(
+            vm.interpret("(nil ?? 9223372036854775807) + 1"),
+            InterpretResult::RuntimeError
+        );
+    }
+
+    #[test]
+    fn test_conditional_short_circuits() {
+        let mut vm = VM::new();
 
-        vm.push(Value::Bool(true));
-        vm.push(Value::Number(25.0));
-        assert_eq!(vm.peek(1), &Value::Bool(true));
-        assert_eq!(vm.pop(), Value::Number(25.0));
-        assert_eq!(vm.pop(), Value::Bool(true));
+        assert_eq!(vm.interpret("true ? 1 : -\"x\""), InterpretResult::Ok);
+        assert_eq!(
+            vm.interpret("false ? 1 : -\"x\""),
+            InterpretResult::RuntimeError
+        );
+        assert_eq!(vm.interpret("nil ? 1 : false ? 2 : 3"), InterpretResult::Ok);
+        assert_eq!(vm.interpret("1 ?? -\"x\""), InterpretResult::Ok);
+        assert_eq!(vm.interpret("nil ?? -\"x\""), InterpretResult::RuntimeError);
+        assert_eq!(vm.interpret("true ? 1"), InterpretResult::CompileError);
     }
 }